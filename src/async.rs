@@ -1,4 +1,8 @@
-use std::{process::Stdio, sync::Arc, time::Duration};
+use std::{
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use color_eyre::eyre::eyre;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
@@ -10,16 +14,37 @@ use tokio::{
 };
 use tracing::{error, warn};
 
-use crate::{ARGS, Command, Result};
+use crate::{ARGS, Command, Job, Result};
 
-pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
-    let count = match ARGS.parallel {
+/// The maximum number of ffmpeg processes to run at once
+pub fn concurrency() -> usize {
+    match ARGS.parallel {
         None => num_cpus::get(),
         Some(x) => {
             // ensure we don't create more processes than cores
             std::cmp::min(x, num_cpus::get())
         }
-    };
+    }
+}
+
+/// The number of threads each of `job_count` jobs should use, so that running jobs together use
+/// all of the CPU without oversubscribing it
+pub fn threads_per_job(job_count: usize) -> usize {
+    let running = concurrency().min(job_count).max(1);
+    (num_cpus::get() / running).max(1)
+}
+
+/// How a command that ran to completion went
+#[derive(Debug, Clone, Copy)]
+pub struct Outcome {
+    pub status: ExitStatus,
+    pub started: SystemTime,
+    pub finished: SystemTime,
+}
+
+/// Runs the commands, returning the jobs that ran to completion (successfully or not)
+pub async fn run_commands(commands: Vec<Command>) -> Result<Vec<(Job, Outcome)>> {
+    let count = concurrency();
 
     let sem = Arc::new(Semaphore::new(count));
     let mut js = JoinSet::new();
@@ -54,13 +79,12 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
              eprintln!("\nCtrl-C received, stopping...");
              let _ = tx.send(());
              while js.join_next().await.is_some() {}
-             Ok(())
+             Ok(Vec::new())
         }
         ret = async {
             for Command {
                 inner: mut command,
-                length,
-                filename,
+                job,
             } in commands
             {
                 let permit = sem.clone().acquire_owned().await?;
@@ -71,6 +95,7 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
 
                 // move closures be like
                 let handle = command.spawn()?;
+                let started = SystemTime::now();
                 let mpb = mpb.clone();
                 let overall_pb = overall_pb.clone();
                 let mut rx = tx.subscribe();
@@ -81,7 +106,7 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
                     let reader = BufReader::new(stdout);
                     let mut lines = reader.lines();
 
-                    let pb = ProgressBar::new(length.as_micros() as _);
+                    let pb = ProgressBar::new(job.length.as_micros() as _);
                     if let Some(overall_pb) = &overall_pb {
                         mpb.insert_before(overall_pb, pb.clone());
                     } else {
@@ -96,7 +121,7 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
                         .progress_chars("=>-"),
                     );
                     pb.set_message(
-                        filename
+                        job.filename
                             .file_name()
                             .expect("Output should always have a name")
                             .to_string_lossy()
@@ -138,7 +163,15 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
                             overall_pb.inspect(|pb| pb.inc(1));
                             pb.finish_and_clear();
                             drop(permit);
-                            ret.map_err(|e| e.into())
+                            ret.map(|status| {
+                                let outcome = Outcome {
+                                    status,
+                                    started,
+                                    finished: SystemTime::now(),
+                                };
+                                (job, outcome)
+                            })
+                            .map_err(|e| e.into())
                         }
                         _ = rx.recv() => {
                             let _ = handle.kill().await;
@@ -151,8 +184,10 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
                 });
             }
 
-            while let Some(status) = js.join_next().await {
-                let status = status??;
+            let mut finished = Vec::new();
+            while let Some(ret) = js.join_next().await {
+                let (job, outcome) = ret??;
+                let status = outcome.status;
                 if !status.success() {
                     let msg = match status.code() {
                         Some(234) => "usually caused by a problematic stream in the source file",
@@ -160,8 +195,9 @@ pub async fn run_commands(commands: Vec<Command>) -> Result<()> {
                     };
                     error!("Command failed with status code {}: {}", status.code().unwrap_or(-1), msg);
                 }
+                finished.push((job, outcome));
             }
-            Ok(())
+            Ok(finished)
         } => ret,
     }
 }
@@ -2,54 +2,35 @@ use std::collections::HashMap;
 use std::iter::Iterator;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::ARGS;
-use crate::input::FieldOrder;
+use crate::input;
+use crate::input::Colour;
+use crate::input::Deinterlace;
+use crate::input::DeinterlaceMode;
+use crate::input::DynamicRange;
+use crate::input::Scale;
 use crate::input::Stream;
 use crate::input::StreamMappings;
+use crate::input::Video;
+use crate::interface::DefaultSubtitle;
+use crate::interface::Deinterlacer;
+use crate::interface::FrameRateMode;
+use crate::interface::ScaleAlgorithm;
+use crate::interface::Tonemap;
 use crate::interface::VideoEncoder;
-use crate::tv::TVOptions;
+use crate::overrides::Overrides;
 use crate::util;
 
 use ffmpeg::codec;
 use itertools::Itertools;
+use once_cell::sync::Lazy;
 use question::Answer;
-use tap::Tap;
+use regex::Regex;
 use tokio::process::Command;
 use tracing::*;
 
-pub fn generate_output_filename<P: AsRef<Path>>(
-    path: P,
-    tv_options: &Option<TVOptions>,
-    rename_title: Option<&str>,
-) -> String {
-    let path = path.as_ref();
-    if let Some(tv_options) = tv_options {
-        format!(
-            "{} - s{:02}e{:02}.mkv",
-            tv_options.title, tv_options.season, tv_options.episode
-        )
-    } else if let Some(rename_title) = rename_title {
-        rename_title.to_owned().tap_mut(|rename_title| {
-            if !rename_title.ends_with(".mkv") {
-                rename_title.push_str(".mkv")
-            }
-        })
-    } else {
-        let input_filename = path
-            .file_name()
-            .expect("input_filepath should have a filename")
-            .to_str()
-            .unwrap();
-        let input_ext = path
-            .extension()
-            .expect("input_filepath should have an extension")
-            .to_str()
-            .unwrap();
-        input_filename.replace(input_ext, "mkv")
-    }
-}
-
 trait GetEncoderExt {
     fn get_encoder(&self) -> &'static str;
 }
@@ -81,37 +62,69 @@ impl GetEncoderExt for codec::Id {
     }
 }
 
+/// A part of the input to encode, rather than the whole file
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub start: Duration,
+    pub length: Duration,
+}
+
+/// Settings for `generate_ffmpeg_command` that can differ between files, or between runs for the
+/// same file
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions<'a> {
+    pub overrides: &'a Overrides,
+    /// The number of threads filters may use
+    pub threads: usize,
+    pub segment: Option<Segment>,
+    /// The title to write to the output's metadata
+    pub title: Option<&'a str>,
+}
+
+/// Checks whether an output can be written, prompting to overwrite it if it already exists
+pub fn check_output(output_path: &Path) -> Result<(), CommandError> {
+    if ARGS.simulate || !output_path.exists() {
+        return Ok(());
+    }
+    if ARGS.overwrite
+        || util::confirm(
+            &format!(
+                "Output file '{}' already exists. Overwrite?",
+                output_path.display()
+            ),
+            Some(Answer::YES),
+        )
+    {
+        warn!(file = ?output_path.to_string_lossy(), "Output file already exists. Overwriting");
+        Ok(())
+    } else {
+        error!(file = ?output_path.to_string_lossy(), "Output file already exists.");
+        Err(CommandError::FileExists)
+    }
+}
+
 pub fn generate_ffmpeg_command<P: AsRef<Path>>(
     input_path: P,
     associated_subs: &[PathBuf],
     output_path: P,
     mut mappings: StreamMappings,
     target_codecs: HashMap<usize, Option<codec::Id>>,
+    options: EncodeOptions,
 ) -> Result<Command, CommandError> {
+    let EncodeOptions {
+        overrides,
+        threads,
+        segment,
+        title,
+    } = options;
+    let crf = overrides.crf.unwrap_or(ARGS.crf);
+
     let mut command = Command::new(&ARGS.ffmpeg_path);
     command.arg("-hide_banner"); // Remove gpl banner
 
-    if !ARGS.simulate && output_path.as_ref().exists() {
-        if ARGS.overwrite
-            || util::confirm(
-                &format!(
-                    "Output file '{}' already exists. Overwrite?",
-                    output_path.as_ref().display()
-                ),
-                Some(Answer::YES),
-            )
-        {
-            warn!(file = ?output_path.as_ref().to_string_lossy(),
-                "Output file already exists. Overwriting"
-            );
-            command.arg("-y");
-        } else {
-            error!(file = ?output_path.as_ref().to_string_lossy(),
-                "Output file already exists."
-            );
-            return Err(CommandError::FileExists);
-        }
-    }
+    // Existing outputs have already been confirmed with `check_output`, and the outputs of
+    // previews and CRF search samples are always replaced
+    command.arg("-y");
 
     let video_stream = match mappings.video.first() {
         Some(Stream::Video(x)) => x,
@@ -121,12 +134,20 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
         }
     };
 
+    let deinterlace = video_stream.deinterlace();
+    let tonemap = video_stream.tonemap();
+    let scale = video_stream.scale();
+
     // Reencode video if:
     // - The video codec is not the same as the target codec
-    // - `--deinterlace` is passed
+    // - The video is to be deinterlaced
+    // - `--tonemap` is passed and the video is HDR
+    // - `--scale` or `--max-height` is passed and the video isn't already that height
     // - `--force-reencode` is passed
     let reencoding_video = target_codecs[&video_stream.index].is_some()
-        || ARGS.force_deinterlace
+        || deinterlace.is_some()
+        || tonemap.is_some()
+        || scale.is_some()
         || ARGS.reencode_video;
 
     let reencoding_audio = mappings
@@ -194,10 +215,13 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
         flags.join("")
     });
 
-    command.arg("-i");
-    command.arg(input_path.as_ref().as_os_str());
-
-    for path in associated_subs {
+    for path in
+        std::iter::once(input_path.as_ref()).chain(associated_subs.iter().map(|x| x.as_path()))
+    {
+        if let Some(Segment { start, length }) = segment {
+            command.args(["-ss", &start.as_secs_f64().to_string()]);
+            command.args(["-t", &length.as_secs_f64().to_string()]);
+        }
         command.arg("-i");
         command.arg(path.as_os_str());
     }
@@ -229,20 +253,61 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
         generate_codec_args(&mut command, 'v', stream.index(), out_index);
     }
 
-    const NVENC_FLAGS: &[&str] = &["-profile:v", "main", "-b:v", "0", "-rc-lookahead", "32"];
+    // Cover art is mapped after every other stream, so its output indices follow on from the
+    // real video streams. The mkv muxer writes attached pictures out as attachments, which
+    // requires a filename and mimetype.
+    for (i, stream) in mappings.cover_art.iter().enumerate() {
+        let out_index = mappings.video.len() + i;
+        let (ext, mimetype) =
+            input::cover_art_type(stream.codec()).expect("cover art streams have an image codec");
+        command.args([&format!("-c:v:{out_index}"), "copy"]);
+        command.args([&format!("-disposition:v:{out_index}"), "attached_pic"]);
+        command.arg(format!("-metadata:s:v:{out_index}"));
+        let filename = if i == 0 {
+            format!("cover.{ext}")
+        } else {
+            format!("cover_{i}.{ext}")
+        };
+        command.arg(format!("filename={filename}"));
+        command.arg(format!("-metadata:s:v:{out_index}"));
+        command.arg(format!("mimetype={mimetype}"));
+    }
+
+    const NVENC_FLAGS: &[&str] = &["-b:v", "0", "-rc-lookahead", "32"];
 
     const LIBX264_FLAGS: &[&str] = &["-profile:v", "high", "-rc-lookahead", "250"];
 
-    const LIBX265_FLAGS: &[&str] = &["-profile:v", "main10", "-x265-params", "rc-lookahead=250"];
+    const LIBX265_FLAGS: &[&str] = &["-profile:v", "main10"];
+
+    const LIBX265_PARAMS: &[&str] = &["rc-lookahead=250"];
 
     const LIBFDK_AAC_FLAGS: &[&str] = &["-cutoff", "18000", "-vbr", "5"];
 
     if reencoding_video {
+        let colour = &video_stream.colour;
+        let dynamic_range = match output_dynamic_range(colour)? {
+            _ if tonemap.is_some() => DynamicRange::Sdr,
+            x => x,
+        };
+        let hdr = dynamic_range != DynamicRange::Sdr;
+
         // Insert the encoder flags for the video stream
         match ARGS.encoder {
             VideoEncoder::Libx264 => {
+                if hdr {
+                    return Err(CommandError::HdrUnsupported(format!(
+                        "libx264 cannot encode {dynamic_range} video. Use `--encoder libx265` or `--encoder nvenc`"
+                    )));
+                }
+                if colour.bit_depth > 8 {
+                    warn!(
+                        bit_depth = colour.bit_depth,
+                        "Source video is high bit depth. libx264 will reduce it to 8-bit"
+                    );
+                }
+
                 command.arg("-crf");
-                command.arg(ARGS.crf.to_string());
+                command.arg(crf.to_string());
                 command.args(LIBX264_FLAGS);
 
                 if let Some(ref x) = ARGS.tune {
@@ -253,16 +318,51 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
             }
             VideoEncoder::Libx265 => {
                 command.arg("-crf");
-                command.arg(ARGS.crf.to_string());
+                command.arg(crf.to_string());
                 command.args(LIBX265_FLAGS);
+
+                let mut x265_params = LIBX265_PARAMS
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>();
+                if hdr {
+                    command.args(["-pix_fmt", "yuv420p10le"]);
+                    x265_params.extend(x265_hdr_params(colour, dynamic_range));
+                }
+                command.arg("-x265-params");
+                command.arg(x265_params.join(":"));
+
                 if ARGS.tune.is_some() {
                     warn!("Tune is not supported for libx265");
                 }
             }
             VideoEncoder::Nvenc => {
                 command.args(["-rc", "constqp", "-qp"]);
-                command.arg(ARGS.crf.to_string());
+                command.arg(crf.to_string());
                 command.args(NVENC_FLAGS);
+                if hdr || colour.bit_depth > 8 {
+                    command.args(["-profile:v", "main10", "-pix_fmt", "p010le"]);
+                } else {
+                    command.args(["-profile:v", "main"]);
+                }
+            }
+        }
+
+        // Tag the output with the source's colour properties, so players know it's HDR. If it has
+        // been tone-mapped, it's now BT.709.
+        if tonemap.is_some() {
+            command.args(["-color_primaries", "bt709"]);
+            command.args(["-color_trc", "bt709"]);
+            command.args(["-colorspace", "bt709"]);
+        } else if hdr {
+            for (option, value) in [
+                ("-color_primaries", colour.primaries.name()),
+                ("-color_trc", colour.transfer.name()),
+                ("-colorspace", colour.matrix.name()),
+            ] {
+                if let Some(value) = value {
+                    command.args([option, value]);
+                }
             }
         }
 
@@ -270,31 +370,70 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
         command.arg("-preset");
         command.arg(ARGS.preset.to_string());
 
-        // Whether to deinterlace the video.
-        let deinterlace = matches!(video_stream.field_order, FieldOrder::Interlaced)
-            && ARGS.no_deinterlace
-            || ARGS.force_deinterlace;
-
-        // Using an array instead of 2 variables so Iterator::join() can be used.
-        // let mut filter_args = [None; 2];
-        // let [crop_filter, deinterlace_filter] = &mut filter_args;
-
-        // If a crop filter is set, use it.
-        let crop_filter = ARGS.crop.as_ref().map(|x| x.to_string());
-
-        let deinterlace_filter = if deinterlace {
-            const NNEDI_FILTER: &str = "idet,fieldmatch=mode=pc_n_ub:combmatch=full:combpel=70,nnedi=deint=interlaced:pscrn=none:threads=32:weights=";
-
-            trace!("Deinterlacing video");
-            Some(format!("{}{}", NNEDI_FILTER, ARGS.nnedi_weights))
-        } else {
-            None
-        };
+        // Filters are applied per stream, as stream-copied video (e.g. cover art) can't be
+        // filtered, and each kept stream has its own interlacing, size and dynamic range. The crop
+        // is measured on the main stream, so only applies to it.
+        let filters = mappings
+            .video
+            .iter()
+            .enumerate()
+            .filter_map(|(out_index, stream)| {
+                let Stream::Video(video) = stream else {
+                    return None;
+                };
+                if target_codecs[&video.index].is_none() && !ARGS.reencode_video {
+                    return None;
+                }
+                let filters = VideoFilters::new(video, overrides, threads);
+                let filters = if out_index == 0 {
+                    filters
+                } else {
+                    filters.without_crop()
+                };
+                Some((out_index, filters.chain()?))
+            })
+            .collect::<Vec<_>>();
+        if !filters.is_empty() {
+            command.args(["-filter_threads", &threads.to_string()]);
+        }
+        for (out_index, filter) in filters {
+            command.arg(format!("-filter:v:{out_index}"));
+            command.arg(filter);
+        }
 
-        let it = std::iter::once(crop_filter).chain(std::iter::once(deinterlace_filter));
-        if it.clone().any(|x| x.is_some()) {
-            command.arg("-filter:v");
-            command.arg(it.flatten().join(","));
+        for (out_index, stream) in mappings.video.iter().enumerate() {
+            let Stream::Video(video) = stream else {
+                continue;
+            };
+            if target_codecs[&video.index].is_none() && !ARGS.reencode_video {
+                continue;
+            }
+            let ivtc = matches!(
+                video.deinterlace(),
+                Some(Deinterlace {
+                    mode: DeinterlaceMode::Ivtc,
+                    ..
+                })
+            );
+            // `passthrough` keeps the source timestamps, which mkv can store exactly. Forcing a
+            // constant rate on variable rate video makes audio drift out of sync.
+            let fps_mode = match ARGS.frame_rate_mode {
+                FrameRateMode::Cfr => "cfr",
+                FrameRateMode::Vfr => "passthrough",
+                // IVTC always produces a constant rate, whatever the source's timestamps
+                FrameRateMode::Auto if ivtc => "cfr",
+                FrameRateMode::Auto if video.is_vfr => "passthrough",
+                FrameRateMode::Auto => continue,
+            };
+            command.args([format!("-fps_mode:v:{out_index}"), fps_mode.to_owned()]);
+            if fps_mode == "cfr"
+                && let Some(rate) = video.output_frame_rate()
+            {
+                command.args([
+                    format!("-r:v:{out_index}"),
+                    format!("{}/{}", rate.numerator(), rate.denominator()),
+                ]);
+            }
         }
     }
 
@@ -345,39 +484,51 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
         }
     }
 
-    if let Some(lang) = ARGS.default_subtitle_language.as_deref() {
-        match mappings
-            .subtitle
-            .iter()
-            .enumerate()
-            .filter(|(_, stream)| {
-                stream.as_subtitle().and_then(|x| x.lang.as_deref()) == Some(lang)
-            })
-            .map(|(idx, _)| idx)
-            .nth(ARGS.default_subtitle_stream)
-        {
-            None => {
+    // `None` leaves the subtitle dispositions alone, `Some(None)` clears the default flag on every
+    // subtitle stream, and `Some(Some(idx))` makes the `idx`th subtitle stream the default.
+    let default_subtitle = match ARGS.default_subtitle {
+        Some(DefaultSubtitle::Auto) => Some(auto_default_subtitle(&mappings)),
+        Some(DefaultSubtitle::None) => Some(None),
+        None => ARGS.default_subtitle_language.as_deref().and_then(|lang| {
+            let idx = mappings
+                .subtitle
+                .iter()
+                .enumerate()
+                .filter(|(_, stream)| {
+                    stream.as_subtitle().and_then(|x| x.lang.as_deref()) == Some(lang)
+                })
+                .map(|(idx, _)| idx)
+                .nth(ARGS.default_subtitle_stream);
+            if idx.is_none() {
                 error!(
                     filename = ?input_path.as_ref(),
                     "Stream with language {lang} could not be found. Has it been discarded?"
                 );
             }
-            Some(target_stream_idx) => {
-                if mappings.subtitle.len() > 1 {
-                    // Move the chosen stream to the front
-                    mappings.subtitle.swap(0, target_stream_idx);
-                }
+            idx.map(Some)
+        }),
+    };
 
-                // Set the default disposition for all subtitle streams to 0 (not default)
-                for stream_idx in 1..mappings.subtitle.len() {
-                    command.arg(format!("-disposition:s:{}", stream_idx));
-                    command.arg("0");
-                }
+    if let Some(target_stream_idx) = default_subtitle {
+        if let Some(target_stream_idx) = target_stream_idx
+            && mappings.subtitle.len() > 1
+        {
+            // Move the chosen stream to the front
+            mappings.subtitle.swap(0, target_stream_idx);
+        }
 
-                // Then mark the first stream as default
-                command.arg("-disposition:s:0");
-                command.arg("default");
-            }
+        // Clear the default disposition on every subtitle stream except the chosen one, keeping
+        // the forced flag intact so players still pick up forced tracks.
+        for (stream_idx, stream) in mappings.subtitle.iter().enumerate() {
+            let is_forced = stream.as_subtitle().is_some_and(|x| x.is_forced);
+            let is_default = stream_idx == 0 && target_stream_idx.is_some();
+            command.arg(format!("-disposition:s:{}", stream_idx));
+            command.arg(match (is_default, is_forced) {
+                (true, true) => "default+forced",
+                (true, false) => "default",
+                (false, true) => "forced",
+                (false, false) => "0",
+            });
         }
     }
 
@@ -417,12 +568,266 @@ pub fn generate_ffmpeg_command<P: AsRef<Path>>(
         command.arg(format!("title={}", audio_stream.title));
     }
 
+    // Write out any languages inferred by `--detect-language`
+    for (stream_type, streams) in [('a', &mappings.audio), ('s', &mappings.subtitle)] {
+        for (i, stream) in streams.iter().enumerate() {
+            if stream.lang_inferred()
+                && let Some(lang) = stream.lang()
+            {
+                command.arg(format!("-metadata:s:{stream_type}:{i}"));
+                command.arg(format!("language={lang}"));
+            }
+        }
+    }
+
+    if let Some(title) = title {
+        command.args(["-metadata", &format!("title={title}")]);
+    }
+
     command.arg(output_path.as_ref().as_os_str());
 
     Ok(command)
 }
 
-#[derive(Debug)]
+/// The arguments of an ffmpeg command, with the input and output paths replaced by `{input}` and
+/// `{output}`, and without `-y`. These are recorded as the settings an output was made with.
+pub fn settings(command: &Command, inputs: &[&Path], output: &Path) -> String {
+    command
+        .as_std()
+        .get_args()
+        .filter(|&x| x != "-y")
+        .map(|x| {
+            if inputs.iter().any(|path| path.as_os_str() == x) {
+                "{input}".into()
+            } else if output.as_os_str() == x {
+                "{output}".into()
+            } else {
+                shell_escape::escape(x.to_string_lossy())
+            }
+        })
+        .join(" ")
+}
+
+/// Thread counts depend on the machine and the size of the batch, but don't change the output
+static THREADS_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r" -filter_threads \d+|:threads=\d+").unwrap());
+
+/// `settings`, without the arguments that only affect performance. Inputs converted with the same
+/// key produce the same output, whichever batch or machine they were converted in.
+pub fn conversion_key(command: &Command, inputs: &[&Path], output: &Path) -> String {
+    THREADS_REGEX
+        .replace_all(&settings(command, inputs, output), "")
+        .into_owned()
+}
+
+/// Works out the dynamic range of the reencoded video. Dolby Vision can't be carried through a
+/// reencode, so it falls back to whatever its base layer is compatible with, if anything.
+fn output_dynamic_range(colour: &Colour) -> Result<DynamicRange, CommandError> {
+    let dynamic_range = colour.dynamic_range();
+    let Some(dolby_vision) = colour.dolby_vision else {
+        return Ok(dynamic_range);
+    };
+
+    match dolby_vision.fallback() {
+        Some(fallback) => {
+            warn!(
+                profile = dolby_vision.profile,
+                level = dolby_vision.level,
+                "Dolby Vision cannot be preserved when reencoding. Keeping the {fallback} base layer"
+            );
+            Ok(fallback)
+        }
+        None => Err(CommandError::HdrUnsupported(format!(
+            "Dolby Vision profile {} has no HDR10, HLG or SDR compatible base layer, so cannot be reencoded",
+            dolby_vision.profile
+        ))),
+    }
+}
+
+/// The filters applied to reencoded video. Each is `None` if it isn't needed.
+pub struct VideoFilters {
+    crop: Option<String>,
+    deinterlace: Option<String>,
+    scale: Option<String>,
+    tonemap: Option<String>,
+}
+
+impl VideoFilters {
+    pub fn new(video: &Video, overrides: &Overrides, threads: usize) -> Self {
+        // If a crop filter is set, use it.
+        let crop = overrides
+            .crop
+            .as_ref()
+            .or(ARGS.crop.as_ref())
+            .map(|x| x.to_string());
+
+        let deinterlace = video.deinterlace().map(|deinterlace| {
+            trace!(?deinterlace, "Deinterlacing video");
+            deinterlace_filter(deinterlace.mode, threads)
+        });
+
+        let scale = video.scale().map(scale_filter);
+
+        let tonemap = video
+            .tonemap()
+            .map(|tonemap| tonemap_filter(&video.colour, tonemap));
+
+        Self {
+            crop,
+            deinterlace,
+            scale,
+            tonemap,
+        }
+    }
+
+    /// For video other than the main stream, which the crop doesn't apply to
+    pub fn without_crop(self) -> Self {
+        Self { crop: None, ..self }
+    }
+
+    /// The full filter chain, or `None` if there is nothing to do
+    pub fn chain(&self) -> Option<String> {
+        // Scaling must come after deinterlacing, as scaling interlaced frames mixes the fields.
+        // Tone-mapping goes last, so it only has to process the cropped, scaled, progressive
+        // frames.
+        Self::join([&self.crop, &self.deinterlace, &self.scale, &self.tonemap])
+    }
+
+    /// The filters to apply to the source to make it comparable with the output. This is
+    /// everything but scaling, as the output is scaled back to the source's size instead.
+    pub fn reference_chain(&self) -> Option<String> {
+        Self::join([&self.crop, &self.deinterlace, &self.tonemap])
+    }
+
+    fn join<'a>(filters: impl IntoIterator<Item = &'a Option<String>>) -> Option<String> {
+        let filters = filters.into_iter().flatten().collect::<Vec<_>>();
+        (!filters.is_empty()).then(|| filters.into_iter().join(","))
+    }
+}
+
+/// Builds the deinterlacing filter for `--deinterlacer`. In IVTC mode, fields are matched back into
+/// progressive frames, the deinterlacer only cleans up frames that couldn't be matched, and the
+/// duplicate frames are then dropped.
+fn deinterlace_filter(mode: DeinterlaceMode, threads: usize) -> String {
+    // Only frames marked as interlaced (by fieldmatch) are deinterlaced in IVTC mode
+    let frames = match mode {
+        DeinterlaceMode::Deinterlace => "all",
+        DeinterlaceMode::Ivtc => "interlaced",
+    };
+    let deinterlacer = match ARGS.deinterlacer {
+        Deinterlacer::Bwdif => Some(format!("bwdif=mode=send_frame:deint={frames}")),
+        Deinterlacer::Yadif => Some(format!("yadif=mode=send_frame:deint={frames}")),
+        Deinterlacer::Nnedi => Some(format!(
+            "nnedi=deint={frames}:pscrn=none:threads={threads}:weights={}",
+            ARGS.nnedi_weights
+        )),
+        Deinterlacer::None => None,
+    };
+
+    match mode {
+        DeinterlaceMode::Deinterlace => deinterlacer.unwrap_or_default(),
+        DeinterlaceMode::Ivtc => std::iter::once("fieldmatch=order=auto:combmatch=full".to_owned())
+            .chain(deinterlacer)
+            .chain(std::iter::once("decimate".to_owned()))
+            .join(","),
+    }
+}
+
+/// Builds a filter scaling video to the given height. The width is derived from the input's, so the
+/// display aspect ratio is kept (the scale filters adjust the SAR to match), and rounded to an even
+/// number as 4:2:0 chroma needs.
+fn scale_filter(scale: Scale) -> String {
+    let height = match scale {
+        Scale::Exact(height) => height.to_string(),
+        // The input may have been cropped to below the target height, so this can't just compare
+        // against the stream's height
+        Scale::Max(height) => format!("'trunc(min(ih,{height})/2)*2'"),
+    };
+    match ARGS.scale_algorithm {
+        ScaleAlgorithm::Bicubic => format!("scale=w=-2:h={height}:flags=bicubic"),
+        ScaleAlgorithm::Lanczos => format!("scale=w=-2:h={height}:flags=lanczos"),
+        ScaleAlgorithm::Spline36 => format!("zscale=w=-2:h={height}:f=spline36"),
+    }
+}
+
+/// Builds a zscale/tonemap filter chain converting PQ or HLG video to SDR BT.709. Tone-mapping is
+/// done on linear light in floating point, then converted back to limited-range 4:2:0.
+fn tonemap_filter(colour: &Colour, tonemap: Tonemap) -> String {
+    let transfer = colour.transfer.name().unwrap_or("smpte2084");
+    let primaries = colour.primaries.name().unwrap_or("bt2020");
+    let matrix = colour.matrix.name().unwrap_or("bt2020nc");
+    [
+        format!("zscale=tin={transfer}:pin={primaries}:min={matrix}:t=linear:npl=100"),
+        "format=gbrpf32le".to_owned(),
+        "zscale=p=bt709".to_owned(),
+        format!("tonemap=tonemap={tonemap}:desat=0"),
+        "zscale=t=bt709:m=bt709:r=tv".to_owned(),
+        "format=yuv420p".to_owned(),
+    ]
+    .join(",")
+}
+
+/// x265 parameters needed to carry HDR metadata into the output bitstream.
+fn x265_hdr_params(colour: &Colour, dynamic_range: DynamicRange) -> Vec<String> {
+    let mut params = vec!["repeat-headers=1".to_owned()];
+    if let Some(primaries) = colour.primaries.name() {
+        params.push(format!("colorprim={primaries}"));
+    }
+    if let Some(transfer) = colour.transfer.name() {
+        params.push(format!("transfer={transfer}"));
+    }
+    if let Some(matrix) = colour.matrix.name() {
+        params.push(format!("colormatrix={matrix}"));
+    }
+
+    if dynamic_range == DynamicRange::Hdr10 {
+        params.push("hdr10=1".to_owned());
+        params.push("hdr10-opt=1".to_owned());
+        if let Some(mastering_display) = colour.mastering_display {
+            params.push(format!("master-display={}", mastering_display.to_x265()));
+        }
+        if let Some(cll) = colour.content_light_level {
+            params.push(format!("max-cll={},{}", cll.max_cll, cll.max_fall));
+        }
+    }
+
+    params
+}
+
+/// Picks the subtitle stream to mark as default for `--default-subtitle auto`.
+///
+/// If the default (first) audio stream is already in the preferred subtitle language, only a
+/// forced subtitle in that language is chosen, so foreign-language dialogue and signs are still
+/// translated. Otherwise, the first full subtitle stream in the preferred language is chosen.
+fn auto_default_subtitle(mappings: &StreamMappings) -> Option<usize> {
+    let preferred = ARGS.subtitle_languages.first()?.as_str();
+    let audio_lang = mappings
+        .audio
+        .first()
+        .and_then(|x| x.as_audio())
+        .and_then(|x| x.lang.as_deref());
+    let want_forced = audio_lang == Some(preferred);
+
+    let idx = mappings.subtitle.iter().position(|stream| {
+        stream
+            .as_subtitle()
+            .is_some_and(|x| x.lang.as_deref() == Some(preferred) && x.is_forced == want_forced)
+    });
+
+    trace!(
+        ?audio_lang,
+        %preferred,
+        ?idx,
+        "Automatically choosing default subtitle stream"
+    );
+
+    idx
+}
+
+#[derive(Debug, thiserror::Error)]
 pub enum CommandError {
+    #[error("Output file already exists")]
     FileExists,
+    #[error("{0}")]
+    HdrUnsupported(String),
 }
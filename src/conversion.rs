@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::ARGS;
+use crate::input::{Stream, StreamMappings};
+use crate::overrides::Overrides;
+use crate::state::Db;
+
+/// The results of the per-file analysis an output was converted with. These are stored with the
+/// conversion, so an input converted before can be recognised without analysing it again.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Analysis {
+    /// The scan types found by `--detect-interlacing`, by stream index
+    #[serde(default)]
+    pub scan_types: BTreeMap<usize, String>,
+    /// The CRF chosen by `--target-vmaf`, and the target it was chosen for
+    pub crf: Option<u8>,
+    pub target_vmaf: Option<f64>,
+}
+
+impl Analysis {
+    /// Records the analysis of the main video streams in `mappings`
+    pub fn new(mappings: &StreamMappings, crf: Option<u8>) -> Self {
+        let scan_types = mappings
+            .video
+            .iter()
+            .filter_map(|x| match x {
+                Stream::Video(video) => Some((video.index, video.scan_type?.to_string())),
+                _ => None,
+            })
+            .collect();
+        Self {
+            scan_types,
+            crf,
+            target_vmaf: crf.and(ARGS.target_vmaf),
+        }
+    }
+
+    /// Applies the results this run would also have found, i.e. those of analyses it would run
+    fn apply(&self, mappings: &mut StreamMappings, overrides: &mut Overrides) {
+        if ARGS.detect_interlacing {
+            for stream in mappings.video.iter_mut() {
+                if let Stream::Video(video) = stream
+                    && let Some(scan_type) = self.scan_types.get(&video.index)
+                {
+                    video.scan_type = scan_type.parse().ok();
+                }
+            }
+        }
+        if overrides.crf.is_none()
+            && ARGS.target_vmaf.is_some()
+            && self.target_vmaf == ARGS.target_vmaf
+        {
+            overrides.crf = self.crf;
+        }
+    }
+}
+
+/// Finds the output of a previous conversion of the input with this fingerprint, with the same
+/// settings, before it's analysed. Each previous conversion's analysis is applied to `mappings`
+/// and `overrides`, and `key` generates the `command::conversion_key` they give.
+pub fn previous(
+    db: &Db,
+    fingerprint: &str,
+    mappings: &StreamMappings,
+    overrides: &Overrides,
+    key: impl Fn(StreamMappings, &Overrides) -> Option<String>,
+) -> Option<String> {
+    let conversions = db
+        .conversions(fingerprint)
+        .inspect_err(|e| warn!(err = %e, "Failed to look up conversions"))
+        .ok()?;
+    conversions
+        .into_iter()
+        .filter(|(_, output, _)| Path::new(output).exists())
+        .find(|(settings, _, analysis)| {
+            // Conversions recorded before analyses were stored have none
+            let analysis = analysis
+                .as_deref()
+                .and_then(|x| serde_json::from_str::<Analysis>(x).ok())
+                .unwrap_or_default();
+            let (mut mappings, mut overrides) = (mappings.clone(), overrides.clone());
+            analysis.apply(&mut mappings, &mut overrides);
+            key(mappings, &overrides).as_ref() == Some(settings)
+        })
+        .map(|(_, output, _)| output)
+}
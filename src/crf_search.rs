@@ -0,0 +1,190 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, eyre};
+use itertools::Itertools;
+use tokio::runtime::Runtime;
+use tracing::*;
+
+use crate::command::{CommandError, Segment};
+use crate::interface::QualityMetric;
+use crate::quality::{self, Clip};
+use crate::state::Db;
+use crate::{ARGS, Job, util};
+
+/// The CRF values to try. VMAF is close to linear in CRF over this range, so interpolating between
+/// them is accurate to within a point or so.
+const CANDIDATES: [u8; 4] = [16, 20, 24, 28];
+
+/// The number of segments to encode at each CRF
+const SAMPLES: u32 = 4;
+
+/// The result of a search for the CRF that hits `--target-vmaf`
+#[derive(Debug)]
+pub struct CrfSearch {
+    /// The mean VMAF score of the sample segments at each candidate CRF
+    pub samples: Vec<(u8, f64)>,
+    pub crf: u8,
+    /// Whether the samples came from the database, rather than being measured
+    pub cached: bool,
+}
+
+impl Display for CrfSearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CRF search{}: {}. Using CRF {}",
+            if self.cached { " (cached)" } else { "" },
+            self.samples
+                .iter()
+                .map(|(crf, vmaf)| format!("{crf} -> VMAF {vmaf:.2}"))
+                .join(", "),
+            self.crf
+        )
+    }
+}
+
+/// Finds the highest CRF that gives a VMAF score of at least `target`, by encoding sample
+/// segments of the input with `encode(crf, segment, output)`. Results are cached per input and
+/// encoder settings, so changing the target doesn't need any new encodes.
+pub fn search(
+    job: &Job,
+    filters: Option<&str>,
+    target: f64,
+    db: &Db,
+    rt: &Runtime,
+    encode: impl Fn(u8, Segment, &Path) -> Result<tokio::process::Command, CommandError>,
+) -> Result<CrfSearch> {
+    let settings = format!(
+        "{} {} {:?} {}",
+        ARGS.encoder,
+        ARGS.preset,
+        ARGS.tune,
+        filters.unwrap_or_default()
+    );
+    let key = util::file_key(&job.input);
+
+    if let Some((path, size, modified)) = &key {
+        match db.crf_samples(path, *size, *modified, &settings) {
+            Ok(Some(samples)) => {
+                return Ok(CrfSearch {
+                    crf: interpolate(&samples, target),
+                    samples,
+                    cached: true,
+                });
+            }
+            Ok(None) => {}
+            Err(e) => warn!(err = %e, "Failed to read cached CRF search"),
+        }
+    }
+
+    let segments = segments(job.length);
+    let mut samples = Vec::with_capacity(CANDIDATES.len());
+    for crf in CANDIDATES {
+        let mut total = 0.0;
+        for (i, &segment) in segments.iter().enumerate() {
+            let output = std::env::temp_dir().join(format!(
+                "videoconverter-{}-crf{crf}-{i}.mkv",
+                std::process::id()
+            ));
+            let score = encode_and_measure(job, &output, crf, segment, rt, &encode);
+            let _ = std::fs::remove_file(&output);
+            total += score?;
+        }
+        let vmaf = total / segments.len() as f64;
+        debug!(%crf, %vmaf, "Measured CRF sample");
+        samples.push((crf, vmaf));
+    }
+
+    if let Some((path, size, modified)) = &key
+        && let Err(e) = db.write_crf_samples(path, *size, *modified, &settings, &samples)
+    {
+        warn!(err = %e, "Failed to cache CRF search");
+    }
+
+    Ok(CrfSearch {
+        crf: interpolate(&samples, target),
+        samples,
+        cached: false,
+    })
+}
+
+fn encode_and_measure(
+    job: &Job,
+    output: &Path,
+    crf: u8,
+    segment: Segment,
+    rt: &Runtime,
+    encode: impl Fn(u8, Segment, &Path) -> Result<tokio::process::Command, CommandError>,
+) -> Result<f64> {
+    let mut command = encode(crf, segment, output)?;
+    command.stdout(Stdio::null());
+    command.stderr(Stdio::null());
+    trace!(?command, "Encoding CRF sample");
+
+    let status = rt.block_on(command.status())?;
+    if !status.success() {
+        return Err(eyre!("Sample encode at CRF {crf} failed with {status}"));
+    }
+
+    quality::compare(
+        Clip {
+            path: output,
+            start: None,
+            length: None,
+        },
+        Clip {
+            path: &job.input,
+            start: Some(segment.start),
+            length: Some(segment.length),
+        },
+        job.video_index,
+        job.reference_filter.as_deref(),
+        QualityMetric::Vmaf,
+    )
+}
+
+/// Evenly spaced segments of the input, or the whole input if it's too short to sample
+fn segments(length: Duration) -> Vec<Segment> {
+    if length <= quality::SAMPLE_LENGTH * SAMPLES {
+        return vec![Segment {
+            start: Duration::ZERO,
+            length,
+        }];
+    }
+    let spacing = (length - quality::SAMPLE_LENGTH) / (SAMPLES + 1);
+    (1..=SAMPLES)
+        .map(|i| Segment {
+            start: spacing * i,
+            length: quality::SAMPLE_LENGTH,
+        })
+        .collect()
+}
+
+/// Interpolates linearly between the samples either side of the target, rounding down to the
+/// higher quality CRF. Outside the sampled range, the nearest candidate is used.
+fn interpolate(samples: &[(u8, f64)], target: f64) -> u8 {
+    let Some(&(first_crf, first_vmaf)) = samples.first() else {
+        return ARGS.crf;
+    };
+    if first_vmaf < target {
+        warn!(
+            crf = first_crf,
+            vmaf = first_vmaf,
+            target,
+            "Even the lowest sampled CRF doesn't reach the target VMAF"
+        );
+        return first_crf;
+    }
+
+    for (&(low_crf, low_vmaf), &(high_crf, high_vmaf)) in samples.iter().tuple_windows() {
+        if high_vmaf < target {
+            let fraction = (low_vmaf - target) / (low_vmaf - high_vmaf);
+            return low_crf + (fraction * (high_crf - low_crf) as f64).floor() as u8;
+        }
+    }
+
+    samples.last().map(|&(crf, _)| crf).unwrap_or(ARGS.crf)
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::{OptionExt, Result, WrapErr, eyre};
+use serde::{Deserialize, Serialize};
+use tabular::{Table, row};
+
+use crate::episodes::Episode;
+use crate::interface::DbCommand;
+use crate::state::Db;
+use crate::tv::TVOptions;
+
+/// The contents of `db export`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Export {
+    entries: Vec<TVOptions>,
+    /// Episodes and season lengths by show. These are stored separately from the entries, so a
+    /// show may have either without the other.
+    #[serde(default)]
+    shows: BTreeMap<String, ShowEpisodes>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ShowEpisodes {
+    /// Episodes in each season
+    #[serde(default)]
+    seasons: BTreeMap<u32, u32>,
+    #[serde(default)]
+    episodes: Vec<Episode>,
+}
+
+pub fn run(command: &DbCommand, db: &Db) -> Result<()> {
+    match command {
+        DbCommand::List { filter, season } => {
+            let entries = db.entries(filter.as_deref(), *season)?;
+            if entries.is_empty() {
+                println!("No shows found");
+                return Ok(());
+            }
+            let mut table = Table::new("{:<} {:>} {:>}");
+            table.add_row(row!("Title", "Season", "Episode"));
+            table.add_heading("---");
+            for entry in entries {
+                table.add_row(row!(entry.title, entry.season, entry.episode));
+            }
+            println!("{table}");
+            Ok(())
+        }
+        DbCommand::Show { title } => {
+            let entry = db.entry(title)?;
+            let title = entry.as_ref().map_or(title.as_str(), |x| x.title.as_str());
+            let lengths = db.season_lengths(title)?;
+            let episodes = db.episodes(title)?;
+            let history = db.history(Some(title))?;
+            if entry.is_none() && lengths.is_empty() && episodes.is_empty() && history.is_empty() {
+                return Err(eyre!("Nothing stored for '{title}'"));
+            }
+
+            println!("{title}");
+            match entry {
+                Some(entry) => println!(
+                    "  Last processed: s{:02}e{:02}",
+                    entry.season, entry.episode
+                ),
+                None => println!("  Last processed: never"),
+            }
+            if !lengths.is_empty() {
+                let lengths = lengths
+                    .iter()
+                    .map(|(season, episodes)| format!("{season}: {episodes}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!("  Seasons:        {lengths}");
+            }
+            println!("  Episode titles: {}", episodes.len());
+            println!("  Files:          {}", history.len());
+            Ok(())
+        }
+        DbCommand::Edit {
+            title,
+            season,
+            episode,
+        } => {
+            let entry = existing(db, title)?;
+            let entry = TVOptions {
+                season: season.unwrap_or(entry.season),
+                episode: episode.unwrap_or(entry.episode),
+                ..entry
+            };
+            db.write(&entry)?;
+            println!(
+                "Set '{}' to s{:02}e{:02}",
+                entry.title, entry.season, entry.episode
+            );
+            Ok(())
+        }
+        DbCommand::Rename { from, to } => {
+            let entry = existing(db, from)?;
+            if let Some(other) = db.entry(to)?
+                && other.title != entry.title
+            {
+                return Err(eyre!(
+                    "'{}' already exists. Use `db merge` to combine them",
+                    other.title
+                ));
+            }
+            db.rename_show(&entry.title, to)?;
+            println!("Renamed '{}' to '{to}'", entry.title);
+            Ok(())
+        }
+        DbCommand::Merge { into, from } => {
+            let target = db.entry(into)?;
+            let mut merged = Vec::new();
+            for title in from {
+                let entry = existing(db, title)?;
+                if target.as_ref().is_some_and(|x| x.title == entry.title) {
+                    return Err(eyre!("Can't merge '{}' into itself", entry.title));
+                }
+                if !merged.iter().any(|x: &TVOptions| x.title == entry.title) {
+                    merged.push(entry);
+                }
+            }
+
+            let (season, episode) = target
+                .iter()
+                .chain(&merged)
+                .map(|x| (x.season, x.episode))
+                .max()
+                .expect("at least one entry is merged");
+            let into = TVOptions {
+                title: target.map_or_else(|| into.clone(), |x| x.title),
+                season,
+                episode,
+                from_filenames: false,
+            };
+            db.merge_shows(&into, &merged)?;
+            println!(
+                "Merged {} entries into '{}', at s{season:02}e{episode:02}",
+                merged.len(),
+                into.title
+            );
+            Ok(())
+        }
+        DbCommand::Remove { title } => {
+            let entry = existing(db, title)?;
+            db.remove_entry(&entry.title)?;
+            println!("Removed '{}'", entry.title);
+            Ok(())
+        }
+        DbCommand::Export { file } => {
+            let mut export = Export {
+                entries: db.entries(None, None)?,
+                ..Export::default()
+            };
+            for show in db.shows_with_episodes()? {
+                let episodes = ShowEpisodes {
+                    seasons: db.season_lengths(&show)?.into_iter().collect(),
+                    episodes: db.episodes(&show)?,
+                };
+                export.shows.insert(show, episodes);
+            }
+
+            let json = serde_json::to_string_pretty(&export)?;
+            match file {
+                Some(file) => {
+                    std::fs::write(file, json)
+                        .wrap_err_with(|| format!("Failed to write {}", file.display()))?;
+                    println!(
+                        "Exported {} shows to {}",
+                        export.entries.len(),
+                        file.display()
+                    );
+                }
+                None => println!("{json}"),
+            }
+            Ok(())
+        }
+        DbCommand::Import { file } => {
+            let contents = std::fs::read_to_string(file)
+                .wrap_err_with(|| format!("Failed to read {}", file.display()))?;
+            let export: Export = serde_json::from_str(&contents)
+                .wrap_err_with(|| format!("Failed to parse {}", file.display()))?;
+
+            for entry in &export.entries {
+                db.write(entry)?;
+            }
+            for (show, episodes) in &export.shows {
+                db.write_episodes(show, &episodes.episodes)?;
+                let lengths = episodes.seasons.clone().into_iter().collect::<Vec<_>>();
+                db.write_season_lengths(show, &lengths)?;
+            }
+            println!(
+                "Imported {} shows, and episodes for {}",
+                export.entries.len(),
+                export.shows.len()
+            );
+            Ok(())
+        }
+        DbCommand::Vacuum => {
+            db.vacuum()?;
+            println!("Vacuumed the database");
+            Ok(())
+        }
+    }
+}
+
+/// The entry with this title, ignoring case
+fn existing(db: &Db, title: &str) -> Result<TVOptions> {
+    db.entry(title)?
+        .ok_or_eyre(format!("No entry for '{title}'. See `db list`"))
+}
@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use color_eyre::eyre::{OptionExt, Result};
+use ffmpeg::codec;
+use ffmpeg::codec::subtitle::Rect;
+use itertools::Itertools;
+use question::Answer;
+use tracing::*;
+
+use crate::input::Stream;
+use crate::lang::{self, Language};
+use crate::util;
+
+/// The amount of subtitle text to collect before running language identification. A few thousand
+/// characters is plenty for a trigram model, and means we don't decode the whole stream.
+const SAMPLE_CHARS: usize = 4096;
+
+/// Infers languages for audio and subtitle streams that have no `language` tag.
+///
+/// `paths[n]` must be the path of file number `n`, i.e. the input file followed by its
+/// associated subtitle files. Text subtitles are identified from their contents. Audio streams
+/// can't be, so they are guessed from the other streams, the container and the filename, and
+/// the user is asked to confirm the guess.
+pub fn infer_languages(parsed: &mut [Stream], paths: &[&Path]) {
+    for stream in parsed.iter_mut() {
+        if !matches!(stream, Stream::Subtitle(_)) || stream.lang().is_some() {
+            continue;
+        }
+        let (file, index) = (stream.file(), stream.index());
+        if !is_text_subtitle(stream.codec()) {
+            debug!(%file, %index, codec = ?stream.codec(), "Cannot detect language of bitmap subtitles");
+            continue;
+        }
+
+        match detect_subtitle_lang(paths[file], index) {
+            Ok(Some(lang)) => {
+                info!(%file, %index, %lang, "Detected subtitle language");
+                stream.set_inferred_lang(lang);
+            }
+            Ok(None) => {
+                warn!(%file, %index, "Could not reliably detect subtitle language");
+            }
+            Err(e) => {
+                warn!(%file, %index, err = %e, "Failed to read subtitle text");
+            }
+        }
+    }
+
+    let audio_count = parsed.iter().filter(|x| x.as_audio().is_some()).count();
+    let guess = guess_audio_lang(parsed, audio_count, paths[0]);
+
+    for stream in parsed.iter_mut() {
+        if !matches!(stream, Stream::Audio(_)) || stream.lang().is_some() {
+            continue;
+        }
+        let Some((lang, reason)) = guess.as_ref() else {
+            debug!(file = %stream.file(), index = %stream.index(), "No language guess for audio stream");
+            continue;
+        };
+        if util::confirm(
+            &format!(
+                "Audio stream {}:{} has no language tag. Guessed {} from {}. Use it?",
+                stream.file(),
+                stream.index(),
+                lang::full_name(lang),
+                reason
+            ),
+            Some(Answer::YES),
+        ) {
+            stream.set_inferred_lang(lang);
+        }
+    }
+}
+
+/// Guesses the language of untagged audio streams, returning the language and a description of
+/// where the guess came from.
+fn guess_audio_lang(
+    parsed: &[Stream],
+    audio_count: usize,
+    input_path: &Path,
+) -> Option<(&'static str, &'static str)> {
+    // Forced subtitles cover the parts of the audio that *aren't* in its main language, so they
+    // share a language with the audio. Full subtitles usually mean the opposite, e.g. English
+    // subtitles on Japanese audio, so they're not used.
+    if audio_count == 1 {
+        let forced = parsed
+            .iter()
+            .filter_map(|x| x.as_subtitle())
+            .filter(|x| x.is_forced)
+            .filter_map(|x| x.lang.as_deref())
+            .unique()
+            .exactly_one()
+            .ok();
+
+        if let Some(lang) = forced.and_then(Language::find) {
+            return Some((lang.code(), "forced subtitles"));
+        }
+    }
+
+    let container_lang = ffmpeg::format::input(input_path)
+        .ok()
+        .and_then(|file| file.metadata().get("language").and_then(Language::find));
+    if let Some(lang) = container_lang {
+        return Some((lang.code(), "container metadata"));
+    }
+
+    filename_lang(input_path).map(|lang| (lang.code(), "filename"))
+}
+
+/// The language tags used in release names. Other words and codes are too easily confused with
+/// titles and release tags, e.g. `Thai`, `AAC`, `DTS` or `HDR`.
+const FILENAME_LANGUAGES: &[&str] = &[
+    "ENG",
+    "ENGLISH",
+    "JPN",
+    "JAP",
+    "JAPANESE",
+    "FRE",
+    "FRA",
+    "FRENCH",
+    "GER",
+    "DEU",
+    "GERMAN",
+    "ITA",
+    "ITALIAN",
+    "SPA",
+    "SPANISH",
+    "POR",
+    "PORTUGUESE",
+    "RUS",
+    "RUSSIAN",
+    "KOR",
+    "KOREAN",
+    "CHI",
+    "ZHO",
+    "CHINESE",
+    "DUT",
+    "NLD",
+    "DUTCH",
+    "SWE",
+    "SWEDISH",
+    "DAN",
+    "DANISH",
+    "NOR",
+    "NORWEGIAN",
+    "FIN",
+    "FINNISH",
+    "POL",
+    "POLISH",
+    "HIN",
+    "HINDI",
+    "THAI",
+    "TUR",
+    "TURKISH",
+];
+
+/// Looks for a language in a filename, e.g. `Film.2019.FRENCH.1080p.mkv`,
+/// `Show.S01E01.JPN.1080p.mkv` or `Film.eng.mkv`. Tags must be upper case, as scene releases
+/// write them, unless they end the name as in sidecar files, so title words like `Thai` aren't
+/// mistaken for them.
+fn filename_lang(path: &Path) -> Option<&'static Language> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let tokens = stem
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    let last = tokens.len().checked_sub(1)?;
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|&(i, token)| {
+            FILENAME_LANGUAGES.contains(token)
+                || (i == last && FILENAME_LANGUAGES.contains(&token.to_uppercase().as_str()))
+        })
+        .filter_map(|(_, token)| {
+            let token = token.to_uppercase();
+            // `JAP` isn't an ISO 639 code
+            Language::find(if token == "JAP" { "jpn" } else { &token })
+        })
+        .unique()
+        .exactly_one()
+        .ok()
+}
+
+fn is_text_subtitle(codec: codec::Id) -> bool {
+    use codec::Id::*;
+    matches!(codec, ASS | SSA | SUBRIP | TEXT | WEBVTT | MOV_TEXT)
+}
+
+/// Decodes a sample of a text subtitle stream and runs language identification on it. Returns
+/// `None` if the result is not reliable.
+fn detect_subtitle_lang(path: &Path, index: usize) -> Result<Option<&'static str>> {
+    let mut file = ffmpeg::format::input(path)?;
+    let parameters = file
+        .stream(index)
+        .ok_or_eyre("Subtitle stream not found")?
+        .parameters();
+    let mut decoder = codec::Context::from_parameters(parameters)?
+        .decoder()
+        .subtitle()?;
+
+    let mut text = String::new();
+    for (stream, packet) in file.packets() {
+        if stream.index() != index {
+            continue;
+        }
+        let mut subtitle = ffmpeg::Subtitle::new();
+        if !decoder.decode(&packet, &mut subtitle)? {
+            continue;
+        }
+        for rect in subtitle.rects() {
+            match rect {
+                Rect::Text(x) => text.push_str(x.get()),
+                Rect::Ass(x) => text.push_str(&strip_ass(x.get())),
+                _ => {}
+            }
+            text.push(' ');
+        }
+        if text.len() >= SAMPLE_CHARS {
+            break;
+        }
+    }
+
+    let Some(info) = whatlang::detect(&text) else {
+        return Ok(None);
+    };
+    trace!(lang = ?info.lang(), confidence = info.confidence(), "Subtitle language identification");
+    if !info.is_reliable() {
+        return Ok(None);
+    }
+
+    // whatlang uses ISO 639-3, which has no code for Chinese as a whole, only its varieties.
+    Ok(match info.lang() {
+        whatlang::Lang::Cmn => Some("chi"),
+        lang => Language::find(lang.code()).map(Language::code),
+    })
+}
+
+/// Extracts the spoken text from a decoded ASS event, which looks like
+/// `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text`.
+fn strip_ass(event: &str) -> String {
+    let text = event.splitn(9, ',').nth(8).unwrap_or(event);
+    let mut out = String::with_capacity(text.len());
+    let mut in_override = false;
+    for c in text.chars() {
+        match c {
+            '{' => in_override = true,
+            '}' => in_override = false,
+            _ if !in_override => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("\\N", " ")
+        .replace("\\n", " ")
+        .replace("\\h", " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lang(filename: &str) -> Option<&'static str> {
+        filename_lang(Path::new(filename)).map(|x| x.code())
+    }
+
+    #[test]
+    fn release_tags() {
+        assert_eq!(lang("Show.S01E01.JPN.1080p.mkv"), Some("jpn"));
+        assert_eq!(lang("Film.2019.FRENCH.1080p.WEB.x264-GRP.mkv"), Some("fre"));
+        assert_eq!(lang("Film.2019.GERMAN.DL.1080p.mkv"), Some("ger"));
+        assert_eq!(lang("Anime.S01E01.JAP.mkv"), Some("jpn"));
+    }
+
+    #[test]
+    fn trailing_tags() {
+        assert_eq!(lang("Film.eng.mkv"), Some("eng"));
+        assert_eq!(lang("Film (French).mkv"), Some("fre"));
+    }
+
+    #[test]
+    fn audio_and_video_tags() {
+        for filename in [
+            "Film.2019.1080p.AAC.mkv",
+            "Film.2019.1080p.DTS-HD.MA.mkv",
+            "Film.2019.2160p.HDR.mkv",
+            "Film.2019.1080p.SDR.mkv",
+            "Film.2019.MAC.ACE.mkv",
+        ] {
+            assert_eq!(lang(filename), None, "{filename}");
+        }
+    }
+
+    #[test]
+    fn title_words() {
+        assert_eq!(lang("The.Thai.Kickboxer.2019.1080p.mkv"), None);
+        assert_eq!(lang("Dutch.Courage.S01E01.mkv"), None);
+        assert_eq!(lang("Dutch.Courage.S01E01.GERMAN.mkv"), Some("ger"));
+    }
+
+    #[test]
+    fn conflicting_tags() {
+        assert_eq!(lang("Film.ENG.JPN.mkv"), None);
+    }
+}
@@ -1,47 +1,83 @@
-use std::{io, path::PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 use tracing::info;
 
-use crate::{ARGS, tv::TVOptions};
+use crate::{
+    ARGS,
+    movie::Movie,
+    template::{TemplateError, Values},
+    tv::{FileEpisode, TVOptions},
+};
 
+/// The directory output paths are relative to
 pub struct OutputDir(pub PathBuf);
 
 impl OutputDir {
-    pub fn new(tv_options: &Option<TVOptions>, rename_title: &Option<String>) -> Self {
-        Self(if let Some(TVOptions { title, season, .. }) = tv_options {
-            let mut path = ARGS
-                .tv_output_prefix
-                .as_deref()
-                .map(|prefix| {
-                    // prefix + tv title
-                    prefix.join(title)
-                })
-                .unwrap_or_else(|| {
-                    std::env::current_dir().expect("Current working directory should exist")
-                });
-
-            path.push(format!("Season {:02}", season));
-            path
+    pub fn new(tv_options: &Option<TVOptions>, movie_mode: bool) -> Self {
+        let base = || {
+            ARGS.output_path.clone().unwrap_or_else(|| {
+                std::env::current_dir().expect("Current working directory should exist")
+            })
+        };
+
+        Self(if tv_options.is_some() {
+            ARGS.tv_output_prefix.clone().unwrap_or_else(base)
         } else if let Some(output_prefix) = ARGS.movie_output_dir.as_deref()
-            && rename_title.is_some()
+            && movie_mode
         {
             output_prefix.to_owned()
         } else {
-            let mut base_path =
-                std::env::current_dir().expect("Current working directory should exist");
-            base_path.push("newfiles");
-            base_path
+            base().join("newfiles")
         })
     }
 
-    pub fn create(&self) -> io::Result<()> {
-        if self.0.is_dir() {
-            info!(dir = ?self.0, "Directory already exists");
+    /// The path to write the output for `input` to. TV episodes and movies are named with
+    /// `--tv-template` and `--movie-template`, and anything else keeps its filename.
+    pub fn output_path(
+        &self,
+        input: &Path,
+        tv: Option<(&str, FileEpisode)>,
+        movie: Option<&Movie>,
+        episode_title: Option<&str>,
+    ) -> Result<PathBuf, TemplateError> {
+        let values = Values {
+            filename: input.file_stem().map(|x| x.to_string_lossy().into_owned()),
+            ext: Some("mkv".to_owned()),
+            ..Values::default()
+        };
+        let relative = if let Some((show, file_episode)) = tv {
+            ARGS.tv_template().render(&Values {
+                show: Some(show.to_owned()),
+                season: Some(file_episode.season),
+                episode: Some(file_episode.episode),
+                last_episode: file_episode.last_episode,
+                episode_title: episode_title.map(|x| x.to_owned()),
+                ..values
+            })?
+        } else if let Some(movie) = movie {
+            ARGS.movie_template.render(&Values {
+                title: Some(movie.title.clone()),
+                year: movie.year,
+                ..values
+            })?
         } else {
-            std::fs::create_dir_all(&self.0)?;
-            info!(dir = ?self.0, "Created directory");
-        }
+            Path::new(input.file_name().expect("input should have a filename"))
+                .with_extension("mkv")
+        };
+        Ok(self.0.join(relative))
+    }
+}
 
-        Ok(())
+pub fn create(dir: &Path) -> io::Result<()> {
+    if dir.is_dir() {
+        info!(?dir, "Directory already exists");
+    } else {
+        std::fs::create_dir_all(dir)?;
+        info!(?dir, "Created directory");
     }
+
+    Ok(())
 }
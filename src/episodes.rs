@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use color_eyre::eyre::{OptionExt, Result, WrapErr, eyre};
+use serde::{Deserialize, Serialize};
+use tabular::{Table, row};
+use tracing::*;
+
+use crate::interface::EpisodesCommand;
+use crate::state::Db;
+use crate::tv::FileEpisode;
+
+/// An episode's metadata, as imported into the database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Episode {
+    pub season: u32,
+    pub episode: u32,
+    pub title: String,
+    pub absolute: Option<u32>,
+    /// The original air date, as `YYYY-MM-DD`
+    pub aired: Option<String>,
+}
+
+pub fn run(command: &EpisodesCommand, db: &Db) -> Result<()> {
+    match command {
+        EpisodesCommand::Import { file, show } => {
+            let (name, episodes) = parse(file)?;
+            let show = show
+                .clone()
+                .or(name)
+                .ok_or_eyre("The file doesn't name the show. Pass it with `--show`")?;
+            let count = db.write_episodes(&show, &episodes)?;
+            let lengths = season_lengths(&episodes);
+            db.write_season_lengths(&show, &lengths)?;
+            println!(
+                "Imported {count} episodes of '{show}' in {} seasons",
+                lengths.len()
+            );
+            Ok(())
+        }
+        EpisodesCommand::Seasons { show, lengths } if lengths.is_empty() => {
+            let lengths = db.season_lengths(show)?;
+            if lengths.is_empty() {
+                println!("No seasons stored for '{show}'");
+            }
+            for (season, episodes) in lengths {
+                println!("Season {season}: {episodes} episodes");
+            }
+            Ok(())
+        }
+        EpisodesCommand::Seasons { show, lengths } => {
+            let lengths = (1..).zip(lengths.iter().copied()).collect::<Vec<_>>();
+            db.write_season_lengths(show, &lengths)?;
+            println!("Set {} seasons of '{show}'", lengths.len());
+            Ok(())
+        }
+        EpisodesCommand::List { show } => {
+            let episodes = db.episodes(show)?;
+            if episodes.is_empty() {
+                println!("No episodes stored for '{show}'");
+                return Ok(());
+            }
+            let mut table = Table::new("{:>} {:>} {:>} {:<} {:<}");
+            table.add_row(row!("Season", "Episode", "Absolute", "Aired", "Title"));
+            table.add_heading("---");
+            for episode in episodes {
+                table.add_row(row!(
+                    episode.season,
+                    episode.episode,
+                    episode.absolute.map(|x| x.to_string()).unwrap_or_default(),
+                    episode.aired.unwrap_or_default(),
+                    episode.title
+                ));
+            }
+            println!("{table}");
+            Ok(())
+        }
+    }
+}
+
+/// The number of episodes in each season, taken as the highest episode number. Specials aren't
+/// included.
+fn season_lengths(episodes: &[Episode]) -> Vec<(u32, u32)> {
+    let mut lengths = BTreeMap::new();
+    for episode in episodes.iter().filter(|x| x.season != 0) {
+        let length = lengths.entry(episode.season).or_insert(0);
+        *length = episode.episode.max(*length);
+    }
+    lengths.into_iter().collect()
+}
+
+/// Looks up the title of a file's episode. The titles of files containing several episodes are
+/// joined, e.g. `Pilot (1) + Pilot (2)`.
+pub fn title(db: &Db, show: &str, file_episode: FileEpisode) -> Option<String> {
+    let last = file_episode.last_episode.unwrap_or(file_episode.episode);
+    let mut titles = Vec::new();
+    for episode in file_episode.episode..=last {
+        match db.episode_title(show, file_episode.season, episode) {
+            Ok(Some(title)) if !titles.contains(&title) => titles.push(title),
+            Ok(_) => {}
+            Err(e) => {
+                warn!(err = %e, "Failed to look up episode title");
+                return None;
+            }
+        }
+    }
+    (!titles.is_empty()).then(|| titles.join(" + "))
+}
+
+/// Reads the episodes in an export, returning the show's name if the file contains it
+fn parse(path: &Path) -> Result<(Option<String>, Vec<Episode>)> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("csv"));
+
+    let (show, episodes) = if is_csv {
+        parse_csv(&contents)
+    } else {
+        serde_json::from_str::<Export>(&contents)
+            .map(Export::into_episodes)
+            .map_err(|e| eyre!("Not a TVmaze or TheTVDB export: {e}"))
+    }
+    .wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+
+    debug!(?show, count = episodes.len(), "Parsed episodes");
+    Ok((show, episodes))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    /// TheTVDB v4 `/series/{id}/episodes/default`
+    TheTvdb { data: TheTvdbData },
+    /// TVmaze `/shows/{id}?embed=episodes`
+    TvmazeShow {
+        name: String,
+        #[serde(rename = "_embedded")]
+        embedded: TvmazeEmbedded,
+    },
+    /// TVmaze `/shows/{id}/episodes`
+    TvmazeEpisodes(Vec<TvmazeEpisode>),
+}
+
+#[derive(Deserialize)]
+struct TheTvdbData {
+    series: Option<TheTvdbSeries>,
+    episodes: Vec<TheTvdbEpisode>,
+}
+
+#[derive(Deserialize)]
+struct TheTvdbSeries {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TheTvdbEpisode {
+    name: Option<String>,
+    season_number: u32,
+    number: u32,
+    absolute_number: Option<u32>,
+    aired: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TvmazeEmbedded {
+    episodes: Vec<TvmazeEpisode>,
+}
+
+#[derive(Deserialize)]
+struct TvmazeEpisode {
+    name: Option<String>,
+    season: u32,
+    /// `None` for specials
+    number: Option<u32>,
+    airdate: Option<String>,
+}
+
+impl Export {
+    fn into_episodes(self) -> (Option<String>, Vec<Episode>) {
+        let tvmaze = |episodes: Vec<TvmazeEpisode>| {
+            episodes
+                .into_iter()
+                .filter_map(|x| {
+                    Some(Episode {
+                        season: x.season,
+                        episode: x.number?,
+                        title: x.name.filter(|x| !x.is_empty())?,
+                        absolute: None,
+                        aired: x.airdate.filter(|x| !x.is_empty()),
+                    })
+                })
+                .collect()
+        };
+
+        match self {
+            Export::TheTvdb { data } => (
+                data.series.map(|x| x.name),
+                data.episodes
+                    .into_iter()
+                    .filter_map(|x| {
+                        Some(Episode {
+                            season: x.season_number,
+                            episode: x.number,
+                            title: x.name.filter(|x| !x.is_empty())?,
+                            // TheTVDB uses 0 for episodes without an absolute number
+                            absolute: x.absolute_number.filter(|&x| x != 0),
+                            aired: x.aired.filter(|x| !x.is_empty()),
+                        })
+                    })
+                    .collect(),
+            ),
+            Export::TvmazeShow { name, embedded } => (Some(name), tvmaze(embedded.episodes)),
+            Export::TvmazeEpisodes(episodes) => (None, tvmaze(episodes)),
+        }
+    }
+}
+
+/// Parses a CSV file with a header row. If every row has the same `show`, it's returned as the
+/// show's name.
+fn parse_csv(contents: &str) -> Result<(Option<String>, Vec<Episode>)> {
+    let mut lines = contents.lines().filter(|x| !x.trim().is_empty());
+    let header = split_csv_line(lines.next().ok_or_eyre("File is empty")?);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|x| x.trim().eq_ignore_ascii_case(name))
+    };
+    let required = |name: &str| column(name).ok_or_else(|| eyre!("No '{name}' column"));
+    let (season, episode, title) = (
+        required("season")?,
+        required("episode")?,
+        required("title")?,
+    );
+    let (show, absolute, aired) = (column("show"), column("absolute"), column("aired"));
+
+    let mut shows: Vec<String> = Vec::new();
+    let mut episodes = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let fields = split_csv_line(line);
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|x| fields.get(x))
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+        };
+        let number = |index: usize, name: &str| {
+            field(Some(index))
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(|| eyre!("Invalid {name} on row {}", i + 2))
+        };
+
+        if let Some(show) = field(show)
+            && !shows.iter().any(|x| x == show)
+        {
+            shows.push(show.to_owned());
+        }
+        episodes.push(Episode {
+            season: number(season, "season")?,
+            episode: number(episode, "episode")?,
+            title: field(Some(title))
+                .ok_or_else(|| eyre!("Missing title on row {}", i + 2))?
+                .to_owned(),
+            absolute: field(absolute).and_then(|x| x.parse().ok()),
+            aired: field(aired).map(|x| x.to_owned()),
+        });
+    }
+
+    let show = match shows.as_slice() {
+        [show] => Some(show.clone()),
+        [] => None,
+        _ => return Err(eyre!("The file contains more than one show")),
+    };
+    Ok((show, episodes))
+}
+
+/// Splits a line of CSV into fields. Fields may be quoted, with `""` for a literal quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("fields is never empty");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
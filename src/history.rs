@@ -0,0 +1,179 @@
+use color_eyre::eyre::Result;
+use serde_json::json;
+use tabular::{Table, row};
+use tracing::*;
+
+use crate::r#async::Outcome;
+use crate::input::{self, Stream};
+use crate::state::Db;
+use crate::{Job, util};
+
+/// A record of one file processed
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub source: String,
+    pub source_size: i64,
+    /// See `util::partial_hash`
+    pub source_hash: String,
+    pub output: String,
+    pub show: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    /// The ffmpeg arguments, without the input and output paths
+    pub settings: String,
+    /// Unix timestamps
+    pub started: i64,
+    pub finished: i64,
+    /// `None` if ffmpeg was killed by a signal
+    pub exit_status: Option<i32>,
+    pub output_size: Option<i64>,
+    /// A JSON array describing the output's streams
+    pub streams: Option<String>,
+}
+
+/// Records each finished job in the database, along with the fingerprints of successfully
+/// converted inputs so they're skipped next time
+pub fn record(finished: &[(Job, Outcome)], db: &Db) {
+    for (job, outcome) in finished {
+        // The input was fingerprinted when the job was created, as it may have been moved or
+        // deleted since
+        let (source_size, source_hash) = job
+            .fingerprint
+            .as_ref()
+            .map(|x| (x.size as i64, x.hash.clone()))
+            .unwrap_or_default();
+        let succeeded = outcome.status.success();
+
+        let entry = HistoryEntry {
+            source: job.input.to_string_lossy().into_owned(),
+            source_size,
+            source_hash,
+            output: job.filename.to_string_lossy().into_owned(),
+            show: job.show.clone(),
+            season: job.episode.map(|x| x.season),
+            episode: job.episode.map(|x| x.episode),
+            settings: job.settings.clone(),
+            started: util::unix_time(outcome.started),
+            finished: util::unix_time(outcome.finished),
+            exit_status: outcome.status.code(),
+            output_size: std::fs::metadata(&job.filename)
+                .ok()
+                .filter(|_| succeeded)
+                .map(|x| x.len() as i64),
+            streams: succeeded.then(|| stream_layout(job)).flatten(),
+        };
+        if let Err(e) = db.write_history(&entry) {
+            warn!(file = ?job.input, err = %e, "Failed to record history");
+        }
+        if succeeded
+            && let Some(fingerprint) = &job.fingerprint
+            && let Err(e) = db.write_conversion(
+                &fingerprint.to_string(),
+                &job.conversion_key,
+                &entry.output,
+                entry.finished,
+                &serde_json::to_string(&job.analysis).unwrap_or_default(),
+            )
+        {
+            warn!(file = ?job.input, err = %e, "Failed to record conversion");
+        }
+    }
+}
+
+/// Describes the streams in a job's output
+fn stream_layout(job: &Job) -> Option<String> {
+    let file = ffmpeg::format::input(&job.filename)
+        .inspect_err(|e| warn!(file = ?job.filename, err = %e, "Failed to read output"))
+        .ok()?;
+    let streams = input::parse_stream_metadata(file, 0)
+        .iter()
+        .map(|stream| {
+            let kind = match stream {
+                Stream::Video(_) => "video",
+                Stream::Audio(_) => "audio",
+                Stream::Subtitle(_) => "subtitle",
+            };
+            json!({
+                "type": kind,
+                "codec": format!("{:?}", stream.codec()),
+                "language": stream.lang(),
+                "title": stream.get_title(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(serde_json::Value::from(streams).to_string())
+}
+
+/// Prints the files processed for a show, or every file, most recent first
+pub fn run(show: Option<&str>, verbose: bool, db: &Db) -> Result<()> {
+    let entries = db.history(show)?;
+    if entries.is_empty() {
+        match show {
+            Some(show) => println!("No files processed for '{show}'"),
+            None => println!("No files processed"),
+        }
+        return Ok(());
+    }
+
+    if verbose {
+        for entry in entries {
+            println!("{}", entry.output);
+            println!(
+                "  Source:   {} ({})",
+                entry.source,
+                format_size(entry.source_size)
+            );
+            println!("  Started:  {}", util::format_timestamp(entry.started));
+            println!("  Finished: {}", util::format_timestamp(entry.finished));
+            println!("  Status:   {}", status(&entry));
+            println!("  Settings: {}", entry.settings);
+            if let Some(streams) = &entry.streams {
+                println!("  Streams:  {streams}");
+            }
+            println!();
+        }
+        return Ok(());
+    }
+
+    let mut table = Table::new("{:<} {:<} {:<} {:>} {:>} {:<}");
+    table.add_row(row!(
+        "Finished", "Episode", "Status", "Took", "Size", "Output"
+    ));
+    table.add_heading("---");
+    for entry in entries {
+        let episode = match (entry.season, entry.episode) {
+            (Some(season), Some(episode)) => format!("s{season:02}e{episode:02}"),
+            _ => String::new(),
+        };
+        let took = (entry.finished - entry.started).max(0);
+        table.add_row(row!(
+            util::format_timestamp(entry.finished),
+            episode,
+            status(&entry),
+            format!("{}:{:02}:{:02}", took / 3600, took % 3600 / 60, took % 60),
+            entry.output_size.map(format_size).unwrap_or_default(),
+            entry.output
+        ));
+    }
+    println!("{table}");
+    Ok(())
+}
+
+fn status(entry: &HistoryEntry) -> String {
+    match entry.exit_status {
+        Some(0) => "ok".to_owned(),
+        Some(code) => format!("failed ({code})"),
+        None => "killed".to_owned(),
+    }
+}
+
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
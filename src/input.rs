@@ -1,19 +1,32 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::path::Path;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use crate::ARGS;
 use crate::interface::AudioReencodeType;
+use crate::interface::Deinterlacer;
+use crate::interface::Height;
+use crate::interface::MissingLanguage;
 use crate::interface::StreamRef;
+use crate::interface::Tonemap;
 use crate::interface::VideoEncoder;
+use crate::lang;
+use crate::overrides::DeinterlaceOverride;
+use crate::overrides::Overrides;
 
 use ffmpeg::ChannelLayout;
+use ffmpeg::Rational;
 pub use ffmpeg::codec;
 pub use ffmpeg::codec::Context;
 pub use ffmpeg::codec::Parameters;
+use ffmpeg::codec::packet::side_data::Type as SideDataType;
+use ffmpeg::color;
 pub use ffmpeg::format::context::Input;
 use ffmpeg::format::stream::Disposition;
 pub use ffmpeg::media::Type;
+use itertools::Itertools;
 use tracing::*;
 
 #[derive(Debug, Clone, Copy)]
@@ -23,12 +36,105 @@ pub enum FieldOrder {
     Interlaced,
 }
 
+/// How a video stream's frames were made, as determined by analysing its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ScanType {
+    Progressive,
+    Interlaced,
+    /// Progressive film content with 3:2 pulldown applied
+    Telecined,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeinterlaceMode {
+    /// Deinterlace every frame
+    Deinterlace,
+    /// Match fields back into the original progressive frames, and drop the duplicates 3:2
+    /// pulldown adds
+    Ivtc,
+}
+
+/// How a video stream will be deinterlaced
+#[derive(Debug, Clone, Copy)]
+pub struct Deinterlace {
+    pub mode: DeinterlaceMode,
+    /// Whether this was inferred from the stream, rather than forced by an option
+    pub inferred: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Video {
     pub file: usize,
     pub index: usize,
     pub codec: codec::Id,
     pub field_order: FieldOrder,
+    pub width: u32,
+    pub height: u32,
+    pub duration: Option<Duration>,
+    /// The average frame rate
+    pub frame_rate: Option<Rational>,
+    /// Whether the frame rate varies, i.e. the average frame rate doesn't match the base rate,
+    /// other than by field rate or pulldown
+    pub is_vfr: bool,
+    /// Whether this is cover art (a still image attached to the file) rather than real video
+    pub is_cover_art: bool,
+    pub colour: Colour,
+    /// The result of `--detect-interlacing`. Takes precedence over `field_order`
+    pub scan_type: Option<ScanType>,
+    /// The per-file deinterlacing override. Takes precedence over the command line
+    pub deinterlace_override: Option<DeinterlaceOverride>,
+}
+
+/// Colour metadata for a video stream, needed to carry HDR through a reencode.
+#[derive(Debug, Clone)]
+pub struct Colour {
+    pub primaries: color::Primaries,
+    pub transfer: color::TransferCharacteristic,
+    pub matrix: color::Space,
+    pub bit_depth: u8,
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light_level: Option<ContentLightLevel>,
+    pub dolby_vision: Option<DolbyVision>,
+}
+
+/// SMPTE ST 2086 mastering display colour volume
+#[derive(Debug, Clone, Copy)]
+pub struct MasteringDisplay {
+    /// CIE 1931 xy chromaticity of the red, green and blue primaries, in that order
+    pub primaries: [(f64, f64); 3],
+    pub white_point: (f64, f64),
+    /// Minimum luminance in cd/m²
+    pub min_luminance: f64,
+    /// Maximum luminance in cd/m²
+    pub max_luminance: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContentLightLevel {
+    pub max_cll: u32,
+    pub max_fall: u32,
+}
+
+/// The parts of a Dolby Vision decoder configuration record we care about
+#[derive(Debug, Clone, Copy)]
+pub struct DolbyVision {
+    pub profile: u8,
+    pub level: u8,
+    /// Which format the base layer can be decoded as without Dolby Vision support
+    pub bl_compatibility_id: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum DynamicRange {
+    #[strum(serialize = "SDR")]
+    Sdr,
+    #[strum(serialize = "HDR10")]
+    Hdr10,
+    #[strum(serialize = "HLG")]
+    Hlg,
+    #[strum(serialize = "Dolby Vision")]
+    DolbyVision,
 }
 
 #[allow(dead_code)]
@@ -44,6 +150,8 @@ pub struct Audio {
     pub original_title: Option<String>,
     pub title: String,
     pub is_forced: bool,
+    /// Whether `lang` was inferred by `--detect-language` rather than read from the stream's tags
+    pub lang_inferred: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -55,6 +163,8 @@ pub struct Subtitle {
     pub original_title: Option<String>,
     pub title: String,
     pub is_forced: bool,
+    /// Whether `lang` was inferred by `--detect-language` rather than read from the stream's tags
+    pub lang_inferred: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -64,10 +174,17 @@ pub enum Stream {
     Subtitle(Subtitle),
 }
 
+#[derive(Clone)]
 pub struct StreamMappings {
     pub video: Vec<Stream>,
     pub audio: Vec<Stream>,
     pub subtitle: Vec<Stream>,
+    /// Cover art, which is written to the output as mkv attachments
+    pub cover_art: Vec<Stream>,
+    /// Video streams that will not be written to the output
+    pub dropped_video: Vec<Stream>,
+    /// Why each video stream was kept or dropped, keyed by `(file, index)`
+    pub video_reasons: HashMap<(usize, usize), &'static str>,
 }
 
 impl Stream {
@@ -108,10 +225,84 @@ impl Stream {
         index: usize,
         codec_context: Context,
         codec_parameters: Parameters,
+        stream: &ffmpeg::format::stream::Stream,
     ) -> Self {
         let codec = codec_parameters.id();
 
+        let duration = match stream.duration() {
+            x if x > 0 => Some(Duration::from_secs_f64(
+                x as f64 * f64::from(stream.time_base()),
+            )),
+            _ => None,
+        };
+
+        let valid = |rate: Rational| rate.numerator() > 0 && rate.denominator() > 0;
+        let (avg_frame_rate, real_frame_rate) = (stream.avg_frame_rate(), stream.rate());
+        let frame_rate = [avg_frame_rate, real_frame_rate]
+            .into_iter()
+            .find(|&x| valid(x));
+        // Interlaced and soft telecined video has a base rate of the field rate or the pre-pulldown
+        // rate, e.g. 60000/1001 or 30000/1001 against an average of 24000/1001. These ratios
+        // don't mean the rate varies.
+        let is_vfr = valid(avg_frame_rate) && valid(real_frame_rate) && {
+            let (avg, real) = (f64::from(avg_frame_rate), f64::from(real_frame_rate));
+            let ratio = avg.max(real) / avg.min(real);
+            [1.0, 1.25, 2.0, 2.5]
+                .iter()
+                .all(|expected| (ratio - expected).abs() / expected > 0.001)
+        };
+
+        // Some muxers don't set the attached_pic disposition, so also treat single-frame image
+        // streams as cover art. Matroska doesn't store frame counts, so an unknown count (0)
+        // isn't enough.
+        let is_cover_art = stream.disposition().contains(Disposition::ATTACHED_PIC)
+            || (cover_art_type(codec).is_some() && stream.frames() == 1);
+
         let decoder = codec_context.decoder().video();
+        let (width, height) = decoder
+            .as_ref()
+            .map(|x| (x.width(), x.height()))
+            .unwrap_or_default();
+
+        let mut colour = match &decoder {
+            Ok(decoder) => Colour {
+                primaries: decoder.color_primaries(),
+                transfer: decoder.color_transfer_characteristic(),
+                matrix: decoder.color_space(),
+                // SAFETY: the descriptor is a pointer to static data owned by libavutil
+                bit_depth: decoder
+                    .format()
+                    .descriptor()
+                    .map(|x| unsafe { (*x.as_ptr()).comp[0].depth as u8 })
+                    .unwrap_or(8),
+                mastering_display: None,
+                content_light_level: None,
+                dolby_vision: None,
+            },
+            Err(_) => Colour {
+                primaries: color::Primaries::Unspecified,
+                transfer: color::TransferCharacteristic::Unspecified,
+                matrix: color::Space::Unspecified,
+                bit_depth: 8,
+                mastering_display: None,
+                content_light_level: None,
+                dolby_vision: None,
+            },
+        };
+        for side_data in stream.side_data() {
+            match side_data.kind() {
+                SideDataType::MasteringDisplayMetadata => {
+                    colour.mastering_display = parse_mastering_display(side_data.data());
+                }
+                SideDataType::ContentLightLevel => {
+                    colour.content_light_level = parse_content_light_level(side_data.data());
+                }
+                SideDataType::DOVI_CONF => {
+                    colour.dolby_vision = parse_dovi_conf(side_data.data());
+                }
+                _ => {}
+            }
+        }
         // SAFETY: we have a shared reference to the decoder, and field_order is a repr(u32) enum that owns no resources
         let field_order = match unsafe { decoder.map(|x| (*x.as_ptr()).field_order) } {
             Ok(ffmpeg::ffi::AVFieldOrder::AV_FIELD_PROGRESSIVE) => FieldOrder::Progressive,
@@ -132,6 +323,15 @@ impl Stream {
             index,
             codec,
             field_order,
+            width,
+            height,
+            duration,
+            frame_rate,
+            is_vfr,
+            is_cover_art,
+            colour,
+            scan_type: None,
+            deinterlace_override: None,
         })
     }
 
@@ -144,7 +344,7 @@ impl Stream {
         disposition: Disposition,
     ) -> Stream {
         let codec = codec_parameters.id();
-        let lang = tags.get("language").map(|f| f.to_string());
+        let lang = tags.get("language").map(lang::normalise);
         let decoder = codec_context.decoder().audio().unwrap();
         let channel_layout = decoder.channel_layout().to_owned();
         let channels = channel_layout.channels();
@@ -154,16 +354,7 @@ impl Stream {
         };
         let is_forced = disposition.contains(Disposition::FORCED);
         let original_title = tags.get("title").map(|x| x.to_string());
-        let title = if original_title.is_none() || ARGS.normalize_titles {
-            let lang_full = lang.as_deref().map(get_full_langname).unwrap_or("Unknown");
-            let mut title = lang_full.to_string();
-            if is_forced {
-                title.push_str(" [Forced]");
-            }
-            title
-        } else {
-            original_title.as_ref().unwrap().to_string()
-        };
+        let title = stream_title(lang.as_deref(), original_title.as_deref(), is_forced);
 
         Self::Audio(Audio {
             file,
@@ -176,6 +367,7 @@ impl Stream {
             original_title,
             title,
             is_forced,
+            lang_inferred: false,
         })
     }
 
@@ -187,19 +379,10 @@ impl Stream {
         disposition: Disposition,
     ) -> Stream {
         let codec = codec_parameters.id();
-        let lang = tags.get("language").map(|f| f.to_string());
+        let lang = tags.get("language").map(lang::normalise);
         let is_forced = disposition.contains(Disposition::FORCED);
         let original_title = tags.get("title").map(|x| x.to_string());
-        let title = if original_title.is_none() || ARGS.normalize_titles {
-            let lang_full = lang.as_deref().map(get_full_langname).unwrap_or("Unknown");
-            let mut title = lang_full.to_string();
-            if is_forced {
-                title.push_str(" [Forced]");
-            }
-            title
-        } else {
-            original_title.as_ref().unwrap().to_string()
-        };
+        let title = stream_title(lang.as_deref(), original_title.as_deref(), is_forced);
 
         Self::Subtitle(Subtitle {
             file,
@@ -209,6 +392,7 @@ impl Stream {
             original_title,
             title,
             is_forced,
+            lang_inferred: false,
         })
     }
 
@@ -234,14 +418,194 @@ impl Stream {
             _ => None,
         }
     }
+
+    pub fn lang(&self) -> Option<&str> {
+        match self {
+            Stream::Audio(x) => x.lang.as_deref(),
+            Stream::Subtitle(x) => x.lang.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn lang_inferred(&self) -> bool {
+        match self {
+            Stream::Audio(x) => x.lang_inferred,
+            Stream::Subtitle(x) => x.lang_inferred,
+            _ => false,
+        }
+    }
+
+    /// Sets the language of an untagged audio or subtitle stream, regenerating its title to
+    /// match.
+    pub fn set_inferred_lang(&mut self, lang: &str) {
+        let (lang_field, lang_inferred, original_title, title, is_forced) = match self {
+            Stream::Audio(x) => (
+                &mut x.lang,
+                &mut x.lang_inferred,
+                &x.original_title,
+                &mut x.title,
+                x.is_forced,
+            ),
+            Stream::Subtitle(x) => (
+                &mut x.lang,
+                &mut x.lang_inferred,
+                &x.original_title,
+                &mut x.title,
+                x.is_forced,
+            ),
+            Stream::Video(_) => return,
+        };
+        *lang_field = Some(lang.to_owned());
+        *lang_inferred = true;
+        *title = stream_title(Some(lang), original_title.as_deref(), is_forced);
+    }
+}
+
+impl Video {
+    /// The tone-mapping operator to apply to this stream. This is only set if `--tonemap` is
+    /// passed and the stream has a PQ or HLG transfer characteristic.
+    pub fn tonemap(&self) -> Option<Tonemap> {
+        use color::TransferCharacteristic::{ARIB_STD_B67, SMPTE2084};
+
+        ARGS.tonemap
+            .filter(|_| matches!(self.colour.transfer, SMPTE2084 | ARIB_STD_B67))
+    }
+
+    /// How this stream should be deinterlaced, if at all. `--ivtc` and `--force-deinterlace` take
+    /// precedence, otherwise streams detected or flagged as interlaced (or telecined) are
+    /// deinterlaced unless that's disabled.
+    pub fn deinterlace(&self) -> Option<Deinterlace> {
+        let forced = |mode| Deinterlace {
+            mode,
+            inferred: false,
+        };
+
+        let no_deinterlacer = matches!(ARGS.deinterlacer, Deinterlacer::None);
+
+        // Per-file overrides take precedence over the command line
+        match self.deinterlace_override {
+            _ if ARGS.copy_video => return None,
+            Some(DeinterlaceOverride::None) => return None,
+            Some(DeinterlaceOverride::Force) if no_deinterlacer => return None,
+            Some(DeinterlaceOverride::Force) => return Some(forced(DeinterlaceMode::Deinterlace)),
+            Some(DeinterlaceOverride::Ivtc) => return Some(forced(DeinterlaceMode::Ivtc)),
+            Some(DeinterlaceOverride::Auto) | None => {}
+        }
+
+        if ARGS.ivtc {
+            Some(forced(DeinterlaceMode::Ivtc))
+        } else if ARGS.force_deinterlace {
+            Some(forced(DeinterlaceMode::Deinterlace))
+        } else if ARGS.no_deinterlace || no_deinterlacer {
+            None
+        } else {
+            let mode = match (self.scan_type, self.field_order) {
+                (Some(ScanType::Telecined), _) => DeinterlaceMode::Ivtc,
+                (Some(ScanType::Interlaced), _) | (None, FieldOrder::Interlaced) => {
+                    DeinterlaceMode::Deinterlace
+                }
+                _ => return None,
+            };
+            Some(Deinterlace {
+                mode,
+                inferred: true,
+            })
+        }
+    }
+
+    /// The frame rate of the output, which is 4/5 of the input's after inverse telecine (e.g.
+    /// 29.97 to 23.976 fps)
+    pub fn output_frame_rate(&self) -> Option<Rational> {
+        let rate = self.frame_rate?;
+        match self.deinterlace() {
+            Some(Deinterlace {
+                mode: DeinterlaceMode::Ivtc,
+                ..
+            }) => Some(Rational::new(rate.numerator() * 4, rate.denominator() * 5).reduce()),
+            _ => Some(rate),
+        }
+    }
+
+    /// How this stream should be scaled, if at all. `--max-height` only applies to streams taller
+    /// than it, and `--scale` only to streams that aren't already that height.
+    pub fn scale(&self) -> Option<Scale> {
+        if let Some(Height(height)) = ARGS.scale {
+            return (height != self.height).then_some(Scale::Exact(height));
+        }
+        ARGS.max_height
+            .filter(|&Height(height)| self.height > height)
+            .map(|Height(height)| Scale::Max(height))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Scale {
+    /// Scale to exactly this height
+    Exact(u32),
+    /// Scale down to this height, if the (cropped) video is taller than it
+    Max(u32),
+}
+
+impl Scale {
+    pub fn height(self) -> u32 {
+        match self {
+            Scale::Exact(x) | Scale::Max(x) => x,
+        }
+    }
+}
+
+impl Colour {
+    pub fn dynamic_range(&self) -> DynamicRange {
+        use color::TransferCharacteristic::{ARIB_STD_B67, SMPTE2084};
+
+        match (self.dolby_vision, self.transfer) {
+            (Some(_), _) => DynamicRange::DolbyVision,
+            (None, SMPTE2084) => DynamicRange::Hdr10,
+            (None, ARIB_STD_B67) => DynamicRange::Hlg,
+            (None, _) => DynamicRange::Sdr,
+        }
+    }
+}
+
+impl MasteringDisplay {
+    /// Formats this in the form taken by x265's `master-display` option, which uses units of
+    /// 0.00002 for chromaticity and 0.0001 cd/m² for luminance.
+    pub fn to_x265(self) -> String {
+        let xy = |(x, y): (f64, f64)| format!("({:.0},{:.0})", x * 50000.0, y * 50000.0);
+        let [r, g, b] = self.primaries;
+        format!(
+            "G{}B{}R{}WP{}L({:.0},{:.0})",
+            xy(g),
+            xy(b),
+            xy(r),
+            xy(self.white_point),
+            self.max_luminance * 10000.0,
+            self.min_luminance * 10000.0
+        )
+    }
+}
+
+impl DolbyVision {
+    /// The format of the base layer when the Dolby Vision enhancement is discarded, or `None` if
+    /// the base layer is not usable on its own (e.g. profile 5's IPTPQc2).
+    pub fn fallback(self) -> Option<DynamicRange> {
+        match self.bl_compatibility_id {
+            1 | 6 => Some(DynamicRange::Hdr10),
+            2 => Some(DynamicRange::Sdr),
+            4 => Some(DynamicRange::Hlg),
+            _ => None,
+        }
+    }
 }
 
 impl StreamMappings {
+    /// Iterates over every stream that will be mapped to the output, in output order
     pub fn iter(&self) -> impl Iterator<Item = &Stream> {
         self.video
             .iter()
             .chain(self.audio.iter())
             .chain(self.subtitle.iter())
+            .chain(self.cover_art.iter())
     }
 }
 
@@ -262,6 +626,7 @@ pub fn parse_stream_metadata(file: Input, fileno: usize) -> Vec<Stream> {
                     index,
                     codec_context,
                     codec_parameters,
+                    &stream,
                 )),
                 Type::Audio => Some(Stream::audio(
                     fileno,
@@ -284,25 +649,50 @@ pub fn parse_stream_metadata(file: Input, fileno: usize) -> Vec<Stream> {
         .collect()
 }
 
-pub fn get_stream_mappings(parsed: &[Stream]) -> StreamMappings {
+pub fn get_stream_mappings(
+    parsed: &[Stream],
+    input_path: &Path,
+    overrides: &Overrides,
+) -> Result<StreamMappings, MappingError> {
+    let override_audio = overrides
+        .override_audio
+        .as_ref()
+        .unwrap_or(&ARGS.override_audio);
+    let override_subs = overrides
+        .override_subs
+        .as_ref()
+        .unwrap_or(&ARGS.override_subs);
+
     let mut videos = Vec::new();
     let mut audios = Vec::new();
     let mut subtitles = Vec::new();
+    let mut cover_art = Vec::new();
+    let mut dropped_video = Vec::new();
+    let mut video_reasons = HashMap::new();
 
     for stream in parsed {
         match stream {
-            Stream::Video(x) => {
-                if x.codec != codec::Id::MJPEG {
-                    videos.push(Stream::Video(x.clone()));
+            Stream::Video(x) if x.is_cover_art => {
+                let key = (x.file, x.index);
+                if ARGS.discard_attachments {
+                    video_reasons.insert(key, "cover art, discarded by --discard-attachments");
+                    dropped_video.push(stream.clone());
+                } else if cover_art_type(x.codec).is_none() {
+                    video_reasons.insert(key, "cover art in a format mkv can't attach");
+                    dropped_video.push(stream.clone());
+                } else {
+                    video_reasons.insert(key, "cover art, kept as an attachment");
+                    cover_art.push(stream.clone());
                 }
             }
 
+            Stream::Video(x) => {
+                videos.push(x.clone());
+            }
+
             Stream::Audio(x) => {
-                if !ARGS.override_audio.is_empty() {
-                    if ARGS
-                        .override_audio
-                        .contains(&StreamRef::new(x.file, x.index))
-                    {
+                if !override_audio.is_empty() {
+                    if override_audio.contains(&StreamRef::new(x.file, x.index)) {
                         audios.push(Stream::Audio(x.clone()));
                     }
                 } else if ARGS.all_streams {
@@ -315,11 +705,8 @@ pub fn get_stream_mappings(parsed: &[Stream]) -> StreamMappings {
             }
 
             Stream::Subtitle(x) => {
-                if !ARGS.override_subs.is_empty() {
-                    if ARGS
-                        .override_subs
-                        .contains(&StreamRef::new(x.file, x.index))
-                    {
+                if !override_subs.is_empty() {
+                    if override_subs.contains(&StreamRef::new(x.file, x.index)) {
                         subtitles.push(Stream::Subtitle(x.clone()));
                     }
                 } else if ARGS.all_streams {
@@ -333,37 +720,207 @@ pub fn get_stream_mappings(parsed: &[Stream]) -> StreamMappings {
         }
     }
 
-    match videos.len() {
-        0..=1 => {}
-        n => {
-            warn!(%n, "File has multiple video streams. Only the first stream will be kept");
-            videos.truncate(1);
+    // Pick the main video stream by resolution, then duration, then position in the file.
+    let main = videos
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, x)| (x.width * x.height, x.duration, Reverse(*i)))
+        .map(|(i, _)| i);
+    if let Some(main) = main {
+        let main = videos.remove(main);
+        let main_reason = if videos.is_empty() {
+            "only video stream"
+        } else if videos
+            .iter()
+            .all(|x| x.width * x.height < main.width * main.height)
+        {
+            "main video stream, highest resolution"
+        } else if videos.iter().all(|x| x.duration < main.duration) {
+            "main video stream, longest"
+        } else {
+            "main video stream, first of equals"
+        };
+        video_reasons.insert((main.file, main.index), main_reason);
+
+        for x in &videos {
+            let reason = if ARGS.keep_video_streams {
+                "additional video stream, kept by --keep-video-streams"
+            } else if x.width * x.height < main.width * main.height {
+                "lower resolution than the main video stream"
+            } else if x.duration < main.duration {
+                "shorter than the main video stream"
+            } else {
+                "not the main video stream"
+            };
+            video_reasons.insert((x.file, x.index), reason);
         }
-    }
 
-    if audios.is_empty() {
-        warn!("No english audio streams found. Retaining all audio streams");
-        for stream in parsed {
-            if let Stream::Audio(x) = stream {
-                audios.push(Stream::Audio(x.clone()));
-            }
+        if !videos.is_empty() && !ARGS.keep_video_streams {
+            warn!(
+                n = videos.len() + 1,
+                "File has multiple video streams. Only the main stream will be kept"
+            );
+            dropped_video.extend(videos.drain(..).map(Stream::Video));
         }
+        videos.insert(0, main);
     }
+    let videos = videos.into_iter().map(Stream::Video).collect();
 
-    if subtitles.is_empty() {
-        warn!("No english subtitle streams found. Retaining all subtitle streams");
-        for stream in parsed {
-            if let Stream::Subtitle(x) = stream {
-                subtitles.push(Stream::Subtitle(x.clone()));
-            }
-        }
+    // The missing language policy only applies to files that have streams of the kind, just not
+    // in the requested languages
+    if audios.is_empty() && parsed.iter().any(|x| x.as_audio().is_some()) {
+        audios = fallback_streams(
+            parsed.iter().filter(|x| x.as_audio().is_some()),
+            ARGS.missing_audio_language(),
+            "audio",
+            &ARGS.audio_languages,
+            input_path,
+        )?;
     }
 
-    StreamMappings {
+    if subtitles.is_empty() && parsed.iter().any(|x| x.as_subtitle().is_some()) {
+        subtitles = fallback_streams(
+            parsed.iter().filter(|x| x.as_subtitle().is_some()),
+            ARGS.missing_subtitle_language(),
+            "subtitle",
+            &ARGS.subtitle_languages,
+            input_path,
+        )?;
+    }
+
+    Ok(StreamMappings {
         video: videos,
         audio: audios,
         subtitle: subtitles,
+        cover_art,
+        dropped_video,
+        video_reasons,
+    })
+}
+
+fn read_rational(data: &[u8], offset: usize) -> Option<f64> {
+    let num = i32::from_ne_bytes(data.get(offset..offset + 4)?.try_into().ok()?);
+    let den = i32::from_ne_bytes(data.get(offset + 4..offset + 8)?.try_into().ok()?);
+    Some(if den == 0 {
+        0.0
+    } else {
+        num as f64 / den as f64
+    })
+}
+
+/// Parses an `AVMasteringDisplayMetadata`, which is laid out as `AVRational display_primaries[3][2]`,
+/// `AVRational white_point[2]`, `AVRational min_luminance`, `AVRational max_luminance`,
+/// `int has_primaries`, `int has_luminance`.
+fn parse_mastering_display(data: &[u8]) -> Option<MasteringDisplay> {
+    let has_primaries = i32::from_ne_bytes(data.get(80..84)?.try_into().ok()?) != 0;
+    let has_luminance = i32::from_ne_bytes(data.get(84..88)?.try_into().ok()?) != 0;
+    if !has_primaries || !has_luminance {
+        return None;
     }
+    let xy = |i: usize| {
+        Some((
+            read_rational(data, i * 16)?,
+            read_rational(data, i * 16 + 8)?,
+        ))
+    };
+    Some(MasteringDisplay {
+        primaries: [xy(0)?, xy(1)?, xy(2)?],
+        white_point: xy(3)?,
+        min_luminance: read_rational(data, 64)?,
+        max_luminance: read_rational(data, 72)?,
+    })
+}
+
+/// Parses an `AVContentLightMetadata`, which is two `unsigned`s: MaxCLL and MaxFALL.
+fn parse_content_light_level(data: &[u8]) -> Option<ContentLightLevel> {
+    Some(ContentLightLevel {
+        max_cll: u32::from_ne_bytes(data.get(0..4)?.try_into().ok()?),
+        max_fall: u32::from_ne_bytes(data.get(4..8)?.try_into().ok()?),
+    })
+}
+
+/// Parses an `AVDOVIDecoderConfigurationRecord`, which starts with the `uint8_t`s
+/// `dv_version_major`, `dv_version_minor`, `dv_profile`, `dv_level`, `rpu_present_flag`,
+/// `el_present_flag`, `bl_present_flag` and `dv_bl_signal_compatibility_id`.
+fn parse_dovi_conf(data: &[u8]) -> Option<DolbyVision> {
+    Some(DolbyVision {
+        profile: *data.get(2)?,
+        level: *data.get(3)?,
+        bl_compatibility_id: *data.get(7)?,
+    })
+}
+
+/// The file extension and mimetype to use when attaching a cover art stream to an mkv, or `None`
+/// if the codec isn't a still image format.
+pub fn cover_art_type(codec: codec::Id) -> Option<(&'static str, &'static str)> {
+    match codec {
+        codec::Id::MJPEG => Some(("jpg", "image/jpeg")),
+        codec::Id::PNG => Some(("png", "image/png")),
+        codec::Id::BMP => Some(("bmp", "image/bmp")),
+        _ => None,
+    }
+}
+
+/// Chooses which streams of one type to keep when none are in the requested languages,
+/// according to the `--missing-language` policy.
+fn fallback_streams<'a>(
+    streams: impl Iterator<Item = &'a Stream>,
+    policy: MissingLanguage,
+    kind: &'static str,
+    requested: &[String],
+    input_path: &Path,
+) -> Result<Vec<Stream>, MappingError> {
+    let languages = requested.iter().map(|x| lang::full_name(x)).join(", ");
+    let file = input_path.display();
+
+    let kept = match policy {
+        MissingLanguage::KeepAll => {
+            warn!(
+                "No {kind} streams in {languages} found in '{file}'. Retaining all {kind} streams"
+            );
+            streams.cloned().collect()
+        }
+        MissingLanguage::KeepFirst => {
+            warn!(
+                "No {kind} streams in {languages} found in '{file}'. Retaining the first {kind} stream"
+            );
+            streams.take(1).cloned().collect()
+        }
+        MissingLanguage::KeepUndetermined => {
+            warn!(
+                "No {kind} streams in {languages} found in '{file}'. Retaining {kind} streams with no language"
+            );
+            let kept = streams
+                .filter(|x| matches!(x.lang(), None | Some("und")))
+                .cloned()
+                .collect::<Vec<_>>();
+            if kept.is_empty() {
+                warn!(
+                    "'{file}' has no {kind} streams with an undetermined language. No {kind} streams will be kept"
+                );
+            }
+            kept
+        }
+        MissingLanguage::Fail => {
+            return Err(MappingError::MissingLanguage {
+                kind,
+                languages,
+                path: input_path.to_owned(),
+            });
+        }
+    };
+
+    Ok(kept)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MappingError {
+    #[error("No {kind} streams in {languages} found in '{}'", path.display())]
+    MissingLanguage {
+        kind: &'static str,
+        languages: String,
+        path: PathBuf,
+    },
 }
 
 #[rustfmt::skip]
@@ -406,8 +963,16 @@ pub fn get_codec_mapping(stream_mappings: &StreamMappings) -> HashMap<usize, Opt
         .map(|stream| {
             let index = stream.index();
             match stream {
+                Stream::Video(video) if video.is_cover_art => (index, None),
                 Stream::Video(video) if !ARGS.copy_video => match video.codec {
-                    HEVC | H264 if !ARGS.reencode_video => (index, None),
+                    HEVC | H264
+                        if !ARGS.reencode_video
+                            && video.deinterlace().is_none()
+                            && video.tonemap().is_none()
+                            && video.scale().is_none() =>
+                    {
+                        (index, None)
+                    }
                     _ => (
                         index,
                         Some(match ARGS.encoder {
@@ -450,19 +1015,17 @@ pub fn length(input_filepath: impl AsRef<Path>) -> Duration {
     }
 }
 
-fn get_full_langname(lang_code: &str) -> &str {
-    match lang_code {
-        "eng" => "English",
-        "spa" => "Spanish",
-        "fra" => "French",
-        "deu" => "German",
-        "ita" => "Italian",
-        "jpn" => "Japanese",
-        "chi" => "Chinese",
-        "rus" => "Russian",
-        other => {
-            warn!("Unknown language code: {}", other);
-            other
+/// Generates the title for an audio or subtitle stream. The original title is kept unless there
+/// isn't one or `--normalize-titles` is passed, in which case the language name is used.
+fn stream_title(lang: Option<&str>, original_title: Option<&str>, is_forced: bool) -> String {
+    match original_title {
+        Some(original_title) if !ARGS.normalize_titles => original_title.to_owned(),
+        _ => {
+            let mut title = lang.map(lang::full_name).unwrap_or("Unknown").to_owned();
+            if is_forced {
+                title.push_str(" [Forced]");
+            }
+            title
         }
     }
 }
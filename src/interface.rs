@@ -1,14 +1,24 @@
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use clap::Parser;
 use clap::ValueEnum;
 use clap::builder::ArgPredicate;
 use regex::Regex;
 
+use crate::command::Segment;
+use crate::lang;
+use crate::template::Template;
+
 const NNEDI_WEIGHTS_PATH: &str = "~/.ffmpeg/nnedi3_weights.bin";
 const FFMPEG_BIN_PATH: &str = "ffmpeg";
+pub(crate) const DEFAULT_TV_TEMPLATE: &str = "{show}/Season {season:02}/{show} - s{season:02}e{episode:02}[-e{last_episode:02}][ - {episode_title}].{ext}";
+/// Without `--tv-output-prefix`, episodes are written to the current directory, which is usually
+/// already the show's
+pub(crate) const DEFAULT_TV_TEMPLATE_NO_PREFIX: &str = "Season {season:02}/{show} - s{season:02}e{episode:02}[-e{last_episode:02}][ - {episode_title}].{ext}";
+const DEFAULT_MOVIE_TEMPLATE: &str = "{title}[ ({year})]/{title}[ ({year})].{ext}";
 
 #[derive(Parser, Debug)]
 #[clap(name = "videoconverter", version, author)]
@@ -21,6 +31,11 @@ pub struct Args {
     #[clap(long, default_value = "20")]
     pub crf: u8,
 
+    /// Choose the CRF for each file by encoding samples at several CRF values and interpolating
+    /// to the one that gives this VMAF score. Needs an ffmpeg built with libvmaf
+    #[clap(long, value_name = "SCORE", conflicts_with_all = &["crf", "copy_video"])]
+    pub target_vmaf: Option<f64>,
+
     /// Specify a crop filter. These are of the format `crop=height:width:x:y`
     #[clap(long)]
     pub crop: Option<CropFilter>,
@@ -33,35 +48,99 @@ pub struct Args {
     #[clap(short = 'd', long, conflicts_with = "no_deinterlace")]
     pub force_deinterlace: bool,
 
+    /// The deinterlacing filter to use. `none` disables deinterlacing, like `--no-deinterlace`
+    #[clap(long, default_value = "nnedi", ignore_case = true, value_enum)]
+    pub deinterlacer: Deinterlacer,
+
+    /// Decode a sample of each video to detect whether it is interlaced or telecined, rather than
+    /// trusting the container's field order. Results are cached
+    #[clap(long, conflicts_with_all = &["no_deinterlace", "copy_video"])]
+    pub detect_interlacing: bool,
+
+    /// Inverse telecine video, recovering progressive frames from 3:2 pulldown film content. Any
+    /// frames that can't be matched are deinterlaced with `--deinterlacer`
+    #[clap(long, conflicts_with_all = &["no_deinterlace", "force_deinterlace"])]
+    pub ivtc: bool,
+
+    /// Tone-map HDR video (PQ or HLG) to SDR BT.709 with the given operator. SDR video is
+    /// unaffected
+    #[clap(
+        long,
+        value_name = "OPERATOR",
+        ignore_case = true,
+        value_enum,
+        conflicts_with = "copy_video"
+    )]
+    pub tonemap: Option<Tonemap>,
+
+    /// How to time output video frames. `cfr` outputs a constant frame rate, duplicating or
+    /// dropping frames as needed. `vfr` keeps the source's timestamps. `auto` keeps the timestamps
+    /// of variable frame rate sources, and outputs a constant frame rate after inverse telecine
+    #[clap(long, default_value = "auto", ignore_case = true, value_enum)]
+    pub frame_rate_mode: FrameRateMode,
+
+    /// Downscale video taller than this height, e.g. `1080` or `720p`. Aspect ratio is preserved
+    #[clap(long, value_name = "HEIGHT", conflicts_with_all = &["scale", "copy_video"])]
+    pub max_height: Option<Height>,
+
+    /// Scale video to this height, e.g. `720p`. Aspect ratio is preserved
+    #[clap(long, value_name = "HEIGHT", conflicts_with = "copy_video")]
+    pub scale: Option<Height>,
+
+    /// The algorithm to use when scaling video
+    #[clap(long, default_value = "lanczos", ignore_case = true, value_enum)]
+    pub scale_algorithm: ScaleAlgorithm,
+
     /// Force reencoding of video
     #[clap(long)]
     pub reencode_video: bool,
 
     /// Disable reencoding of video
-    #[clap(long, conflicts_with_all = &["reencode_video", "force_deinterlace"])]
+    #[clap(long, conflicts_with_all = &["reencode_video", "force_deinterlace", "ivtc"])]
     pub copy_video: bool,
 
     /// Control the reencoding of audio
     #[clap(long, default_value = "pcm")]
     pub audio_reencoding: AudioReencodeType,
 
-    /// Audio languages to keep, in the form of ISO 639-2 codes
+    /// Audio languages to keep, as ISO 639 codes (any of 639-1, 639-2/B or 639-2/T) or English
+    /// names
     #[clap(
         long("audio-lang"),
         default_value("eng"),
+        value_parser = lang::parse_language,
         conflicts_with("all_streams"),
         default_values_if("anime", ArgPredicate::IsPresent, &["jpn", "eng"])
     )]
     pub audio_languages: Vec<String>,
 
-    /// Subtitle languages to keep, in the form of ISO 639-2 codes
+    /// Subtitle languages to keep, as ISO 639 codes (any of 639-1, 639-2/B or 639-2/T) or English
+    /// names
     #[clap(
         long("subtitle-lang"),
         default_value("eng"),
+        value_parser = lang::parse_language,
         conflicts_with("all_streams")
     )]
     pub subtitle_languages: Vec<String>,
 
+    /// What to do with a file that has no audio or subtitle streams in the requested languages
+    #[clap(long, value_name = "POLICY", default_value = "keep-all", value_enum)]
+    pub missing_language: MissingLanguage,
+
+    /// Overrides `--missing-language` for audio streams
+    #[clap(long, value_name = "POLICY", value_enum)]
+    pub missing_audio_language: Option<MissingLanguage>,
+
+    /// Overrides `--missing-language` for subtitle streams
+    #[clap(long, value_name = "POLICY", value_enum)]
+    pub missing_subtitle_language: Option<MissingLanguage>,
+
+    /// Infer the language of audio and subtitle streams with no language tag. Text subtitles are
+    /// identified from their contents; guesses for audio streams must be confirmed.
+    #[clap(long)]
+    pub detect_language: bool,
+
     /// Enable reencoding of subtitles
     #[clap(long)]
     pub reencode_subs: bool,
@@ -98,7 +177,7 @@ pub struct Args {
     #[clap(default_value = ".")]
     pub path: Vec<PathBuf>,
 
-    /// The directory to generate `newfiles` in (or Season XX in TV mode)
+    /// The directory to generate `newfiles` in (or TV shows, without `--tv-output-prefix`)
     #[clap(long)]
     pub output_path: Option<PathBuf>,
 
@@ -110,10 +189,27 @@ pub struct Args {
     #[clap(long, env = "VIDEOCONVERTER_MOVIE_OUTPUT_DIR")]
     pub movie_output_dir: Option<PathBuf>,
 
+    /// The path of TV episodes relative to the TV output directory. Fields are `{show}`,
+    /// `{season}`, `{episode}`, `{last_episode}` (for files with several episodes),
+    /// `{episode_title}` and `{ext}`, and `[...]` is left out if a field in it has no value.
+    /// Defaults to `{show}/Season {season:02}/{show} - s{season:02}e{episode:02}...` with
+    /// `--tv-output-prefix`, and `Season {season:02}/...` without it
+    #[clap(long, env = "VIDEOCONVERTER_TV_TEMPLATE")]
+    tv_template: Option<Template>,
+
+    /// The path of movies relative to the movie output directory. Fields are `{title}`,
+    /// `{year}` and `{ext}`
+    #[clap(long, env = "VIDEOCONVERTER_MOVIE_TEMPLATE", default_value = DEFAULT_MOVIE_TEMPLATE)]
+    pub movie_template: Template,
+
     /// Enables renaming of files to TV show format
     #[clap(long, short = 'T')]
     pub tv_mode: bool,
 
+    /// Enables naming of files as movies, with the title and year parsed from each filename
+    #[clap(long, short = 'M', conflicts_with = "tv_mode")]
+    pub movie_mode: bool,
+
     /// The season number to use in TV mode
     #[clap(long, required_if_eq("tv_mode", "true"))]
     pub season: Option<u32>,
@@ -139,15 +235,28 @@ pub struct Args {
     #[clap(
         long,
         value_name = "LANGUAGE",
+        value_parser = lang::parse_language,
         default_value_if("anime", ArgPredicate::IsPresent, "jpn")
     )]
     pub default_audio_language: Option<String>,
 
     /// Moves the `default_subtitle_stream`th subtitle stream with the given language code to the front, and marks it as
     /// default.
-    #[clap(long, value_name = "LANGUAGE")]
+    #[clap(long, value_name = "LANGUAGE", value_parser = lang::parse_language)]
     pub default_subtitle_language: Option<String>,
 
+    /// Choose the default subtitle stream without an explicit language and index. `auto` marks a
+    /// forced subtitle as default if the default audio stream is in the first subtitle language,
+    /// and a full subtitle stream in that language otherwise.
+    #[clap(
+        long,
+        value_name = "MODE",
+        ignore_case = true,
+        value_enum,
+        conflicts_with = "default_subtitle_language"
+    )]
+    pub default_subtitle: Option<DefaultSubtitle>,
+
     /// Works in conjunction with `default_subtitle_language`.
     #[clap(long, value_name = "INDEX", default_value = "0")]
     pub default_audio_stream: usize,
@@ -160,6 +269,35 @@ pub struct Args {
     #[clap(long, default_value = NNEDI_WEIGHTS_PATH)]
     pub nnedi_weights: String,
 
+    /// After encoding, measure the quality of each output against its source with this metric.
+    /// Can be specified multiple times
+    #[clap(
+        long = "quality-metric",
+        value_name = "METRIC",
+        ignore_case = true,
+        value_enum
+    )]
+    pub quality_metrics: Vec<QualityMetric>,
+
+    /// Measure quality over this many evenly spaced 10 second segments, rather than the whole
+    /// file
+    #[clap(long, value_name = "COUNT")]
+    pub quality_samples: Option<usize>,
+
+    /// Flag outputs scoring below a threshold, e.g. `vmaf=93` or `ssim=0.98`. Can be specified
+    /// multiple times
+    #[clap(long = "quality-threshold", value_name = "METRIC=SCORE")]
+    pub quality_thresholds: Vec<QualityThreshold>,
+
+    /// Encode a short sample of the first input into `previews/` with the same settings before
+    /// the full encode, e.g. `60s@25%` for a minute starting a quarter of the way through
+    #[clap(long, value_name = "LENGTH@POSITION")]
+    pub preview: Option<Preview>,
+
+    /// Encode a preview of every input, rather than just the first
+    #[clap(long, requires = "preview")]
+    pub preview_all: bool,
+
     /// Path to ffmpeg binary
     #[clap(long, default_value = FFMPEG_BIN_PATH)]
     pub ffmpeg_path: PathBuf,
@@ -168,6 +306,11 @@ pub struct Args {
     #[clap(long)]
     pub overwrite: bool,
 
+    /// Convert inputs again even if they've already been converted with the same settings. Inputs
+    /// are recognised by their contents, so renamed or moved files are skipped too.
+    #[clap(long)]
+    pub force: bool,
+
     /// File extension to ignore. Can be specified multiple times.
     #[clap(long = "ignore", action = clap::ArgAction::Append, value_name = "EXTENSION")]
     pub ignored_extensions: Vec<String>,
@@ -185,6 +328,11 @@ pub struct Args {
     #[clap(long = "continue")]
     pub continue_processing: bool,
 
+    /// A file of per-file overrides, keyed by glob patterns. These apply after those in a
+    /// `.videoconverter.toml` file next to the input
+    #[clap(long, value_name = "FILE", env = "VIDEOCONVERTER_OVERRIDES")]
+    pub overrides: Option<PathBuf>,
+
     /// If passed, this will override the logic of which audio streams to keep
     #[clap(long)]
     pub override_audio: Vec<StreamRef>,
@@ -201,6 +349,11 @@ pub struct Args {
     #[clap(long, conflicts_with = "all_streams")]
     pub discard_attachments: bool,
 
+    /// Keep every video stream rather than only the main one, e.g. for multi-angle discs. Cover
+    /// art is handled separately, and is always kept unless `--discard-attachments` is passed
+    #[clap(long)]
+    pub keep_video_streams: bool,
+
     /// Normalize all stream titles to their language
     #[clap(long)]
     pub normalize_titles: bool,
@@ -213,17 +366,149 @@ pub struct Args {
     #[clap(long)]
     pub drop_stream: Vec<usize>,
 
-    /// Dump the contents of the sqlite db
-    #[clap(long)]
-    pub dump_db: bool,
+    #[clap(subcommand)]
+    pub command: Option<Subcommand>,
+}
 
-    /// Remove the specified entry from the sqlite db
-    #[clap(long, value_name = "TITLE")]
-    pub remove_db_entry: Option<String>,
+#[derive(Debug, clap::Subcommand)]
+pub enum Subcommand {
+    /// Manage the episode titles used to name TV episodes
+    #[clap(subcommand)]
+    Episodes(EpisodesCommand),
+
+    /// Manage the TV shows stored in the state database
+    #[clap(subcommand)]
+    Db(DbCommand),
+
+    /// Show the files processed for a TV show, or every file, most recent first
+    History {
+        show: Option<String>,
+
+        /// Show the source, settings and output streams of each file
+        #[clap(long, short)]
+        verbose: bool,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum DbCommand {
+    /// List the TV shows stored, with the last episode processed
+    List {
+        /// Only list shows whose titles contain this, ignoring case
+        filter: Option<String>,
+
+        /// Only list shows last processed in this season
+        #[clap(long)]
+        season: Option<u32>,
+    },
+
+    /// Show everything stored for a TV show
+    Show { title: String },
+
+    /// Set the last season and episode processed for a TV show
+    Edit {
+        title: String,
+
+        #[clap(long, required_unless_present = "episode")]
+        season: Option<u32>,
+
+        #[clap(long)]
+        episode: Option<u32>,
+    },
+
+    /// Rename a TV show, including its episodes and history
+    Rename { from: String, to: String },
+
+    /// Merge duplicate entries for a TV show into one. The merged entry carries on from whichever
+    /// was furthest along
+    Merge {
+        into: String,
+
+        #[clap(required = true)]
+        from: Vec<String>,
+    },
+
+    /// Remove a TV show's entry
+    Remove { title: String },
+
+    /// Export the TV shows, episodes and season lengths stored as JSON
+    Export {
+        /// Defaults to stdout
+        file: Option<PathBuf>,
+    },
+
+    /// Import TV shows, episodes and season lengths from a JSON export, replacing any already
+    /// stored with the same titles
+    Import { file: PathBuf },
+
+    /// Compact the database
+    Vacuum,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum EpisodesCommand {
+    /// Import episode titles from a TVmaze or TheTVDB JSON export, or a CSV file with `season`,
+    /// `episode` and `title` columns (and optionally `show`, `absolute` and `aired`)
+    Import {
+        file: PathBuf,
+
+        /// The show to store the episodes under, as entered in TV mode. Defaults to the name in
+        /// the file
+        #[clap(long)]
+        show: Option<String>,
+    },
+
+    /// List the episode titles stored for a show
+    List { show: String },
+
+    /// Set the number of episodes in each season of a show, starting from season 1, or show them
+    /// if none are given. These are used to convert absolute episode numbers, and are set
+    /// automatically when importing episodes
+    Seasons {
+        show: String,
+
+        #[clap(value_name = "EPISODES")]
+        lengths: Vec<u32>,
+    },
 }
 
 impl Args {
+    /// `--tv-template`, or the default for whether `--tv-output-prefix` was given
+    pub fn tv_template(&self) -> Template {
+        self.tv_template.clone().unwrap_or_else(|| {
+            let default = match self.tv_output_prefix {
+                Some(_) => DEFAULT_TV_TEMPLATE,
+                None => DEFAULT_TV_TEMPLATE_NO_PREFIX,
+            };
+            default
+                .parse()
+                .expect("the default template should be valid")
+        })
+    }
+
+    pub fn missing_audio_language(&self) -> MissingLanguage {
+        self.missing_audio_language.unwrap_or(self.missing_language)
+    }
+
+    pub fn missing_subtitle_language(&self) -> MissingLanguage {
+        self.missing_subtitle_language
+            .unwrap_or(self.missing_language)
+    }
+
     pub fn validate(&self) {
+        for threshold in &self.quality_thresholds {
+            if !self.quality_metrics.contains(&threshold.metric) {
+                eprintln!(
+                    "`--quality-threshold {0}=...` requires `--quality-metric {0}`",
+                    threshold.metric
+                );
+                std::process::exit(1);
+            }
+        }
+        if self.force_deinterlace && matches!(self.deinterlacer, Deinterlacer::None) {
+            eprintln!("`--force-deinterlace` cannot be used with `--deinterlacer none`");
+            std::process::exit(1);
+        }
         if matches!(self.encoder, VideoEncoder::Nvenc) {
             if self.no_hwaccel {
                 eprintln!("Hardware acceleration cannot be disabled when using nvenc");
@@ -273,6 +558,136 @@ impl FromStr for CropFilter {
     }
 }
 
+/// A video height, as a number of lines (`720`) or a resolution name (`720p`, `4k`).
+#[derive(Debug, Clone, Copy)]
+pub struct Height(pub u32);
+
+impl FromStr for Height {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        let height = match s.as_str() {
+            "4k" | "uhd" => 2160,
+            "hd" => 720,
+            "fhd" => 1080,
+            _ => s
+                .strip_suffix('p')
+                .unwrap_or(&s)
+                .parse()
+                .map_err(|_| "Height must be a number of lines, e.g. `1080` or `720p`")?,
+        };
+        if height == 0 || height % 2 != 0 {
+            return Err("Height must be a non-zero even number");
+        }
+        Ok(Self(height))
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Deinterlacer {
+    Bwdif,
+    Yadif,
+    Nnedi,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum QualityMetric {
+    Vmaf,
+    Ssim,
+    Psnr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QualityThreshold {
+    pub metric: QualityMetric,
+    pub score: f64,
+}
+
+impl FromStr for QualityThreshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (metric, score) = s
+            .split_once('=')
+            .ok_or("Quality threshold must be of the form <metric>=<score>")?;
+        Ok(QualityThreshold {
+            metric: QualityMetric::from_str(metric, true)?,
+            score: score
+                .parse()
+                .map_err(|_| "Parse failed for quality score")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Preview {
+    pub length: Duration,
+    /// How far into the input to start, as a fraction of its length
+    pub position: f64,
+}
+
+impl Preview {
+    /// The part of an input of length `total` to encode, shortened if it would run past the end
+    pub fn segment(&self, total: Duration) -> Segment {
+        let start = total.mul_f64(self.position);
+        Segment {
+            start,
+            length: self.length.min(total - start),
+        }
+    }
+}
+
+impl FromStr for Preview {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (length, position) = s
+            .split_once('@')
+            .ok_or("Preview must be of the form <length>@<position>, e.g. `60s@25%`")?;
+
+        let (length, multiplier) = match length.strip_suffix('m') {
+            Some(minutes) => (minutes, 60.0),
+            None => (length.strip_suffix('s').unwrap_or(length), 1.0),
+        };
+        let length = length
+            .parse::<f64>()
+            .ok()
+            .filter(|x| x.is_finite() && *x > 0.0)
+            .ok_or("Preview length must be a positive number of seconds or minutes")?;
+
+        let position = position
+            .strip_suffix('%')
+            .unwrap_or(position)
+            .parse::<f64>()
+            .ok()
+            .filter(|x| (0.0..100.0).contains(x))
+            .ok_or("Preview position must be a percentage from 0 to 100")?;
+
+        Ok(Self {
+            length: Duration::from_secs_f64(length * multiplier),
+            position: position / 100.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FrameRateMode {
+    Auto,
+    Cfr,
+    Vfr,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ScaleAlgorithm {
+    Bicubic,
+    Lanczos,
+    Spline36,
+}
+
 #[derive(Debug, ValueEnum, Clone, strum::Display)]
 #[strum(serialize_all = "lowercase")]
 pub enum VideoEncoder {
@@ -335,6 +750,35 @@ impl FromStr for StreamRef {
     }
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Tonemap {
+    Hable,
+    Mobius,
+    Reinhard,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum DefaultSubtitle {
+    /// Mark a forced subtitle as default if the audio is already in the preferred language,
+    /// otherwise mark a full subtitle in the preferred language as default
+    Auto,
+    /// Mark no subtitle stream as default
+    None,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MissingLanguage {
+    /// Keep every stream of that type
+    KeepAll,
+    /// Keep only the first stream of that type
+    KeepFirst,
+    /// Keep only streams with no language tag, or one of `und`
+    KeepUndetermined,
+    /// Skip the file (or abort, without `--continue`)
+    Fail,
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum AudioReencodeType {
@@ -0,0 +1,236 @@
+use std::path::Path;
+
+use color_eyre::eyre::{OptionExt, Result};
+use ffmpeg::codec;
+use tracing::*;
+
+use crate::input::{ScanType, Stream};
+use crate::state::Db;
+use crate::util;
+
+/// The number of frames to decode. This is about 20 seconds of video, which is enough to see a
+/// pulldown cadence repeat many times.
+const SAMPLE_FRAMES: usize = 500;
+
+/// How far into the file to start sampling, as a fraction of its duration. Intros and logos are
+/// often progressive even when the episode isn't.
+const SAMPLE_START: f64 = 0.25;
+
+/// The minimum number of frames with motion needed to classify a stream. Combing can't be seen
+/// in a still image, so static frames say nothing about the content.
+const MIN_MOVING_FRAMES: usize = 50;
+
+/// The mean absolute luma difference from the previous frame above which a frame has motion
+const MOTION_THRESHOLD: f64 = 1.0;
+
+/// The difference between a line and both of its neighbours, in 8-bit luma levels, above which
+/// a pixel is considered combed
+const COMB_THRESHOLD: i32 = 10;
+
+/// The fraction of combed pixels above which a frame is considered combed
+const COMBED_FRAME_RATIO: f64 = 0.02;
+
+/// Classifies the main video streams of `path` as progressive, interlaced or telecined, caching
+/// the result in the database.
+pub fn analyse(parsed: &mut [Stream], path: &Path, db: &Db) {
+    let key = util::file_key(path);
+
+    for stream in parsed.iter_mut() {
+        let Stream::Video(video) = stream else {
+            continue;
+        };
+        if video.file != 0 || video.is_cover_art {
+            continue;
+        }
+
+        if let Some((path, size, modified)) = &key {
+            match db.scan_type(path, *size, *modified, video.index) {
+                Ok(Some(scan_type)) => {
+                    debug!(stream = %video.index, %scan_type, "Using cached interlacing analysis");
+                    video.scan_type = Some(scan_type);
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => warn!(err = %e, "Failed to read cached interlacing analysis"),
+            }
+        }
+
+        match scan_type(path, video.index) {
+            Ok(Some(scan_type)) => {
+                info!(stream = %video.index, %scan_type, "Analysed interlacing");
+                video.scan_type = Some(scan_type);
+                if let Some((path, size, modified)) = &key
+                    && let Err(e) =
+                        db.write_scan_type(path, *size, *modified, video.index, scan_type)
+                {
+                    warn!(err = %e, "Failed to cache interlacing analysis");
+                }
+            }
+            Ok(None) => {
+                warn!(stream = %video.index, "Not enough motion to analyse interlacing");
+            }
+            Err(e) => {
+                warn!(stream = %video.index, err = %e, "Failed to analyse interlacing");
+            }
+        }
+    }
+}
+
+/// Decodes a sample of frames from a video stream and classifies it. Returns `None` if there
+/// wasn't enough motion to tell.
+fn scan_type(path: &Path, index: usize) -> Result<Option<ScanType>> {
+    let mut file = ffmpeg::format::input(path)?;
+    let parameters = file
+        .stream(index)
+        .ok_or_eyre("Video stream not found")?
+        .parameters();
+    let mut decoder = codec::Context::from_parameters(parameters)?
+        .decoder()
+        .video()?;
+
+    let duration = file.duration();
+    if duration > 0 {
+        file.seek((duration as f64 * SAMPLE_START) as i64, ..)?;
+    }
+
+    let mut stats = Stats::default();
+    let mut frame = ffmpeg::frame::Video::empty();
+    'packets: for (stream, packet) in file.packets() {
+        if stream.index() != index {
+            continue;
+        }
+        // Packets before the first keyframe after seeking can't be decoded
+        if decoder.send_packet(&packet).is_err() {
+            continue;
+        }
+        while decoder.receive_frame(&mut frame).is_ok() {
+            stats.push(&frame);
+            if stats.frames.len() >= SAMPLE_FRAMES {
+                break 'packets;
+            }
+        }
+    }
+
+    Ok(stats.classify())
+}
+
+#[derive(Default)]
+struct Stats {
+    /// Whether each decoded frame had motion and whether it was combed
+    frames: Vec<(bool, bool)>,
+    /// The previous frame's luma plane, scaled to 8 bits
+    previous: Vec<u8>,
+}
+
+impl Stats {
+    fn push(&mut self, frame: &ffmpeg::frame::Video) {
+        // SAFETY: the descriptor is a pointer to static data owned by libavutil
+        let depth = frame
+            .format()
+            .descriptor()
+            .map(|x| unsafe { (*x.as_ptr()).comp[0].depth })
+            .unwrap_or(8);
+        let luma = luma_plane(frame, depth);
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+
+        let moving = self.previous.len() == luma.len()
+            && mean_difference(&self.previous, &luma) > MOTION_THRESHOLD;
+        let combed = comb_ratio(&luma, width, height) > COMBED_FRAME_RATIO;
+
+        self.frames.push((moving, combed));
+        self.previous = luma;
+    }
+
+    /// Progressive content has (almost) no combed frames. 3:2 pulldown combs two frames out of
+    /// every five, always at the same points in the cadence, whereas interlaced content combs
+    /// every frame with motion.
+    fn classify(&self) -> Option<ScanType> {
+        let moving = self
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(_, (moving, _))| *moving)
+            .map(|(i, (_, combed))| (i, *combed))
+            .collect::<Vec<_>>();
+        if moving.len() < MIN_MOVING_FRAMES {
+            return None;
+        }
+
+        let combed = moving.iter().filter(|(_, combed)| *combed).count();
+        let combed_ratio = combed as f64 / moving.len() as f64;
+        trace!(moving = moving.len(), combed, "Interlacing statistics");
+        if combed_ratio < 0.05 {
+            return Some(ScanType::Progressive);
+        }
+
+        let mut cadence = [0; 5];
+        for (i, _) in moving.iter().filter(|(_, combed)| *combed) {
+            cadence[i % 5] += 1;
+        }
+        cadence.sort_unstable_by(|a, b| b.cmp(a));
+        trace!(?cadence, "Combed frame cadence");
+        if combed_ratio < 0.6 && (cadence[0] + cadence[1]) as f64 >= 0.8 * combed as f64 {
+            Some(ScanType::Telecined)
+        } else {
+            Some(ScanType::Interlaced)
+        }
+    }
+}
+
+/// Copies the luma plane out of a frame without row padding, scaled to 8 bits
+fn luma_plane(frame: &ffmpeg::frame::Video, depth: i32) -> Vec<u8> {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let (stride, data) = (frame.stride(0), frame.data(0));
+    let bytes_per_sample = if depth > 8 { 2 } else { 1 };
+    if stride == 0 || stride < width * bytes_per_sample {
+        return Vec::new();
+    }
+
+    let mut luma = Vec::with_capacity(width * height);
+    for row in data.chunks(stride).take(height) {
+        let row = &row[..width * bytes_per_sample];
+        if bytes_per_sample == 1 {
+            luma.extend_from_slice(row);
+        } else {
+            luma.extend(
+                row.chunks_exact(2)
+                    .map(|x| (u16::from_le_bytes([x[0], x[1]]) >> (depth - 8)) as u8),
+            );
+        }
+    }
+    luma
+}
+
+fn mean_difference(a: &[u8], b: &[u8]) -> f64 {
+    let total = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (a as i32 - b as i32).unsigned_abs() as u64)
+        .sum::<u64>();
+    total as f64 / a.len().max(1) as f64
+}
+
+/// The fraction of pixels that differ in the same direction from the lines above and below, which
+/// are from the other field. Fields from different moments in time produce this pattern on any
+/// moving edge.
+fn comb_ratio(luma: &[u8], width: usize, height: usize) -> f64 {
+    if height < 3 || luma.len() < width * height {
+        return 0.0;
+    }
+    let mut combed = 0usize;
+    for y in 1..height - 1 {
+        let above = &luma[(y - 1) * width..y * width];
+        let line = &luma[y * width..(y + 1) * width];
+        let below = &luma[(y + 1) * width..(y + 2) * width];
+        combed += above
+            .iter()
+            .zip(line)
+            .zip(below)
+            .filter(|&((&a, &b), &c)| {
+                let (a, b, c) = (a as i32, b as i32, c as i32);
+                (b - a) * (b - c) > COMB_THRESHOLD * COMB_THRESHOLD
+            })
+            .count();
+    }
+    combed as f64 / (width * (height - 2)) as f64
+}
@@ -0,0 +1,596 @@
+use tracing::*;
+
+/// A language from the ISO 639-2 code list, along with its ISO 639-1 equivalent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Language {
+    /// ISO 639-1 two-letter code, if the language has one
+    pub alpha_2: Option<&'static str>,
+    /// ISO 639-2/T (terminology) code
+    pub alpha_3: &'static str,
+    /// ISO 639-2/B (bibliographic) code, if it differs from the terminology code
+    pub bibliographic: Option<&'static str>,
+    /// English names, separated by "; ". The first is the preferred name.
+    names: &'static str,
+}
+
+impl Language {
+    /// The canonical code for this language. This is the ISO 639-2/B code, as that is what
+    /// Matroska uses for its language elements.
+    pub fn code(&self) -> &'static str {
+        self.bibliographic.unwrap_or(self.alpha_3)
+    }
+
+    /// The preferred English name of this language.
+    pub fn name(&self) -> &'static str {
+        self.names.split("; ").next().unwrap_or(self.names)
+    }
+
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        self.names.split("; ")
+    }
+
+    /// Looks up a language by any of its ISO 639-1, ISO 639-2/B or ISO 639-2/T codes, or by its
+    /// English name. Matching is case-insensitive.
+    pub fn find(s: &str) -> Option<&'static Language> {
+        let s = s.trim().to_lowercase();
+        // Tags such as `en-US` or `pt_BR` carry a region, which we don't care about.
+        let code = s.split(['-', '_']).next().unwrap_or(&s);
+
+        LANGUAGES
+            .iter()
+            .find(|lang| {
+                lang.alpha_2 == Some(code)
+                    || lang.alpha_3 == code
+                    || lang.bibliographic == Some(code)
+            })
+            .or_else(|| {
+                LANGUAGES
+                    .iter()
+                    .find(|lang| lang.names().any(|name| name.to_lowercase() == s))
+            })
+    }
+}
+
+/// Normalises a language code or name to its canonical ISO 639-2/B code. Unknown languages are
+/// returned lowercased and otherwise unchanged, so they can still be compared exactly.
+pub fn normalise(s: &str) -> String {
+    match Language::find(s) {
+        Some(lang) => lang.code().to_owned(),
+        None => s.to_lowercase(),
+    }
+}
+
+/// Returns the English name for a language code, falling back to the code itself if it is not
+/// a known language.
+pub fn full_name(code: &str) -> &str {
+    match Language::find(code) {
+        Some(lang) => lang.name(),
+        None => {
+            warn!("Unknown language code: {}", code);
+            code
+        }
+    }
+}
+
+/// Parser for language arguments on the command line. Accepts any form understood by
+/// [`Language::find`], and produces the canonical code.
+pub fn parse_language(s: &str) -> Result<String, String> {
+    Language::find(s)
+        .map(|lang| lang.code().to_owned())
+        .ok_or_else(|| format!("Unknown language '{s}'. Expected an ISO 639 code or English name"))
+}
+
+const fn lang(
+    alpha_2: &'static str,
+    alpha_3: &'static str,
+    bibliographic: &'static str,
+    names: &'static str,
+) -> Language {
+    Language {
+        alpha_2: if alpha_2.is_empty() {
+            None
+        } else {
+            Some(alpha_2)
+        },
+        alpha_3,
+        bibliographic: if bibliographic.is_empty() {
+            None
+        } else {
+            Some(bibliographic)
+        },
+        names,
+    }
+}
+
+// Generated from the ISO 639-2 code list, with a few names reordered so the preferred name reads
+// naturally as a stream title (e.g. "Greek" rather than "Greek, Modern (1453-)").
+#[rustfmt::skip]
+static LANGUAGES: &[Language] = &[
+    //   639-1 639-2/T 639-2/B English name(s)
+    lang("aa", "aar", "",    "Afar"),
+    lang("ab", "abk", "",    "Abkhazian"),
+    lang("",   "ace", "",    "Achinese"),
+    lang("",   "ach", "",    "Acoli"),
+    lang("",   "ada", "",    "Adangme"),
+    lang("",   "ady", "",    "Adyghe; Adygei"),
+    lang("",   "afa", "",    "Afro-Asiatic languages"),
+    lang("",   "afh", "",    "Afrihili"),
+    lang("af", "afr", "",    "Afrikaans"),
+    lang("",   "ain", "",    "Ainu"),
+    lang("ak", "aka", "",    "Akan"),
+    lang("",   "akk", "",    "Akkadian"),
+    lang("",   "ale", "",    "Aleut"),
+    lang("",   "alg", "",    "Algonquian languages"),
+    lang("",   "alt", "",    "Southern Altai"),
+    lang("am", "amh", "",    "Amharic"),
+    lang("",   "ang", "",    "Old English"),
+    lang("",   "anp", "",    "Angika"),
+    lang("",   "apa", "",    "Apache languages"),
+    lang("ar", "ara", "",    "Arabic"),
+    lang("",   "arc", "",    "Official Aramaic (700-300 BCE); Imperial Aramaic (700-300 BCE)"),
+    lang("an", "arg", "",    "Aragonese"),
+    lang("",   "arn", "",    "Mapudungun; Mapuche"),
+    lang("",   "arp", "",    "Arapaho"),
+    lang("",   "art", "",    "Artificial languages"),
+    lang("",   "arw", "",    "Arawak"),
+    lang("as", "asm", "",    "Assamese"),
+    lang("",   "ast", "",    "Asturian; Bable; Leonese; Asturleonese"),
+    lang("",   "ath", "",    "Athapascan languages"),
+    lang("",   "aus", "",    "Australian languages"),
+    lang("av", "ava", "",    "Avaric"),
+    lang("ae", "ave", "",    "Avestan"),
+    lang("",   "awa", "",    "Awadhi"),
+    lang("ay", "aym", "",    "Aymara"),
+    lang("az", "aze", "",    "Azerbaijani"),
+    lang("",   "bad", "",    "Banda languages"),
+    lang("",   "bai", "",    "Bamileke languages"),
+    lang("ba", "bak", "",    "Bashkir"),
+    lang("",   "bal", "",    "Baluchi"),
+    lang("bm", "bam", "",    "Bambara"),
+    lang("",   "ban", "",    "Balinese"),
+    lang("",   "bas", "",    "Basa"),
+    lang("",   "bat", "",    "Baltic languages"),
+    lang("",   "bej", "",    "Beja; Bedawiyet"),
+    lang("be", "bel", "",    "Belarusian"),
+    lang("",   "bem", "",    "Bemba"),
+    lang("bn", "ben", "",    "Bengali"),
+    lang("",   "ber", "",    "Berber languages"),
+    lang("",   "bho", "",    "Bhojpuri"),
+    lang("bh", "bih", "",    "Bihari languages"),
+    lang("",   "bik", "",    "Bikol"),
+    lang("",   "bin", "",    "Bini; Edo"),
+    lang("bi", "bis", "",    "Bislama"),
+    lang("",   "bla", "",    "Siksika"),
+    lang("",   "bnt", "",    "Bantu (Other)"),
+    lang("bo", "bod", "tib", "Tibetan"),
+    lang("bs", "bos", "",    "Bosnian"),
+    lang("",   "bra", "",    "Braj"),
+    lang("br", "bre", "",    "Breton"),
+    lang("",   "btk", "",    "Batak languages"),
+    lang("",   "bua", "",    "Buriat"),
+    lang("",   "bug", "",    "Buginese"),
+    lang("bg", "bul", "",    "Bulgarian"),
+    lang("",   "byn", "",    "Blin; Bilin"),
+    lang("",   "cad", "",    "Caddo"),
+    lang("",   "cai", "",    "Central American Indian languages"),
+    lang("",   "car", "",    "Galibi Carib"),
+    lang("ca", "cat", "",    "Catalan; Valencian"),
+    lang("",   "cau", "",    "Caucasian languages"),
+    lang("",   "ceb", "",    "Cebuano"),
+    lang("",   "cel", "",    "Celtic languages"),
+    lang("cs", "ces", "cze", "Czech"),
+    lang("ch", "cha", "",    "Chamorro"),
+    lang("",   "chb", "",    "Chibcha"),
+    lang("ce", "che", "",    "Chechen"),
+    lang("",   "chg", "",    "Chagatai"),
+    lang("",   "chk", "",    "Chuukese"),
+    lang("",   "chm", "",    "Mari"),
+    lang("",   "chn", "",    "Chinook jargon"),
+    lang("",   "cho", "",    "Choctaw"),
+    lang("",   "chp", "",    "Chipewyan; Dene Suline"),
+    lang("",   "chr", "",    "Cherokee"),
+    lang("cu", "chu", "",    "Church Slavic; Old Slavonic; Church Slavonic; Old Bulgarian; Old Church Slavonic"),
+    lang("cv", "chv", "",    "Chuvash"),
+    lang("",   "chy", "",    "Cheyenne"),
+    lang("",   "cmc", "",    "Chamic languages"),
+    lang("",   "cnr", "",    "Montenegrin"),
+    lang("",   "cop", "",    "Coptic"),
+    lang("kw", "cor", "",    "Cornish"),
+    lang("co", "cos", "",    "Corsican"),
+    lang("",   "cpe", "",    "Creoles and pidgins, English based"),
+    lang("",   "cpf", "",    "Creoles and pidgins, French-based"),
+    lang("",   "cpp", "",    "Creoles and pidgins, Portuguese-based"),
+    lang("cr", "cre", "",    "Cree"),
+    lang("",   "crh", "",    "Crimean Tatar; Crimean Turkish"),
+    lang("",   "crp", "",    "Creoles and pidgins"),
+    lang("",   "csb", "",    "Kashubian"),
+    lang("",   "cus", "",    "Cushitic languages"),
+    lang("cy", "cym", "wel", "Welsh"),
+    lang("",   "dak", "",    "Dakota"),
+    lang("da", "dan", "",    "Danish"),
+    lang("",   "dar", "",    "Dargwa"),
+    lang("",   "day", "",    "Land Dayak languages"),
+    lang("",   "del", "",    "Delaware"),
+    lang("",   "den", "",    "Slave (Athapascan)"),
+    lang("de", "deu", "ger", "German"),
+    lang("",   "dgr", "",    "Dogrib"),
+    lang("",   "din", "",    "Dinka"),
+    lang("dv", "div", "",    "Divehi; Dhivehi; Maldivian"),
+    lang("",   "doi", "",    "Dogri"),
+    lang("",   "dra", "",    "Dravidian languages"),
+    lang("",   "dsb", "",    "Lower Sorbian"),
+    lang("",   "dua", "",    "Duala"),
+    lang("",   "dum", "",    "Dutch, Middle (ca. 1050-1350)"),
+    lang("",   "dyu", "",    "Dyula"),
+    lang("dz", "dzo", "",    "Dzongkha"),
+    lang("",   "efi", "",    "Efik"),
+    lang("",   "egy", "",    "Egyptian (Ancient)"),
+    lang("",   "eka", "",    "Ekajuk"),
+    lang("el", "ell", "gre", "Greek; Modern Greek"),
+    lang("",   "elx", "",    "Elamite"),
+    lang("en", "eng", "",    "English"),
+    lang("",   "enm", "",    "English, Middle (1100-1500)"),
+    lang("eo", "epo", "",    "Esperanto"),
+    lang("et", "est", "",    "Estonian"),
+    lang("eu", "eus", "baq", "Basque"),
+    lang("ee", "ewe", "",    "Ewe"),
+    lang("",   "ewo", "",    "Ewondo"),
+    lang("",   "fan", "",    "Fang"),
+    lang("fo", "fao", "",    "Faroese"),
+    lang("fa", "fas", "per", "Persian"),
+    lang("",   "fat", "",    "Fanti"),
+    lang("fj", "fij", "",    "Fijian"),
+    lang("",   "fil", "",    "Filipino; Pilipino"),
+    lang("fi", "fin", "",    "Finnish"),
+    lang("",   "fiu", "",    "Finno-Ugrian languages"),
+    lang("",   "fon", "",    "Fon"),
+    lang("fr", "fra", "fre", "French"),
+    lang("",   "frm", "",    "French, Middle (ca. 1400-1600)"),
+    lang("",   "fro", "",    "French, Old (842-ca. 1400)"),
+    lang("",   "frr", "",    "Northern Frisian"),
+    lang("",   "frs", "",    "Eastern Frisian"),
+    lang("fy", "fry", "",    "Western Frisian"),
+    lang("ff", "ful", "",    "Fulah"),
+    lang("",   "fur", "",    "Friulian"),
+    lang("",   "gaa", "",    "Ga"),
+    lang("",   "gay", "",    "Gayo"),
+    lang("",   "gba", "",    "Gbaya"),
+    lang("",   "gem", "",    "Germanic languages"),
+    lang("",   "gez", "",    "Geez"),
+    lang("",   "gil", "",    "Gilbertese"),
+    lang("gd", "gla", "",    "Gaelic; Scottish Gaelic"),
+    lang("ga", "gle", "",    "Irish"),
+    lang("gl", "glg", "",    "Galician"),
+    lang("gv", "glv", "",    "Manx"),
+    lang("",   "gmh", "",    "German, Middle High (ca. 1050-1500)"),
+    lang("",   "goh", "",    "German, Old High (ca. 750-1050)"),
+    lang("",   "gon", "",    "Gondi"),
+    lang("",   "gor", "",    "Gorontalo"),
+    lang("",   "got", "",    "Gothic"),
+    lang("",   "grb", "",    "Grebo"),
+    lang("",   "grc", "",    "Ancient Greek"),
+    lang("gn", "grn", "",    "Guarani"),
+    lang("",   "gsw", "",    "Swiss German; Alemannic; Alsatian"),
+    lang("gu", "guj", "",    "Gujarati"),
+    lang("",   "gwi", "",    "Gwich'in"),
+    lang("",   "hai", "",    "Haida"),
+    lang("ht", "hat", "",    "Haitian; Haitian Creole"),
+    lang("ha", "hau", "",    "Hausa"),
+    lang("",   "haw", "",    "Hawaiian"),
+    lang("he", "heb", "",    "Hebrew"),
+    lang("hz", "her", "",    "Herero"),
+    lang("",   "hil", "",    "Hiligaynon"),
+    lang("",   "him", "",    "Himachali languages; Western Pahari languages"),
+    lang("hi", "hin", "",    "Hindi"),
+    lang("",   "hit", "",    "Hittite"),
+    lang("",   "hmn", "",    "Hmong; Mong"),
+    lang("ho", "hmo", "",    "Hiri Motu"),
+    lang("hr", "hrv", "",    "Croatian"),
+    lang("",   "hsb", "",    "Upper Sorbian"),
+    lang("hu", "hun", "",    "Hungarian"),
+    lang("",   "hup", "",    "Hupa"),
+    lang("hy", "hye", "arm", "Armenian"),
+    lang("",   "iba", "",    "Iban"),
+    lang("ig", "ibo", "",    "Igbo"),
+    lang("io", "ido", "",    "Ido"),
+    lang("ii", "iii", "",    "Sichuan Yi; Nuosu"),
+    lang("",   "ijo", "",    "Ijo languages"),
+    lang("iu", "iku", "",    "Inuktitut"),
+    lang("ie", "ile", "",    "Interlingue; Occidental"),
+    lang("",   "ilo", "",    "Iloko"),
+    lang("ia", "ina", "",    "Interlingua (International Auxiliary Language Association)"),
+    lang("",   "inc", "",    "Indic languages"),
+    lang("id", "ind", "",    "Indonesian"),
+    lang("",   "ine", "",    "Indo-European languages"),
+    lang("",   "inh", "",    "Ingush"),
+    lang("ik", "ipk", "",    "Inupiaq"),
+    lang("",   "ira", "",    "Iranian languages"),
+    lang("",   "iro", "",    "Iroquoian languages"),
+    lang("is", "isl", "ice", "Icelandic"),
+    lang("it", "ita", "",    "Italian"),
+    lang("jv", "jav", "",    "Javanese"),
+    lang("",   "jbo", "",    "Lojban"),
+    lang("ja", "jpn", "",    "Japanese"),
+    lang("",   "jpr", "",    "Judeo-Persian"),
+    lang("",   "jrb", "",    "Judeo-Arabic"),
+    lang("",   "kaa", "",    "Kara-Kalpak"),
+    lang("",   "kab", "",    "Kabyle"),
+    lang("",   "kac", "",    "Kachin; Jingpho"),
+    lang("kl", "kal", "",    "Kalaallisut; Greenlandic"),
+    lang("",   "kam", "",    "Kamba"),
+    lang("kn", "kan", "",    "Kannada"),
+    lang("",   "kar", "",    "Karen languages"),
+    lang("ks", "kas", "",    "Kashmiri"),
+    lang("ka", "kat", "geo", "Georgian"),
+    lang("kr", "kau", "",    "Kanuri"),
+    lang("",   "kaw", "",    "Kawi"),
+    lang("kk", "kaz", "",    "Kazakh"),
+    lang("",   "kbd", "",    "Kabardian"),
+    lang("",   "kha", "",    "Khasi"),
+    lang("",   "khi", "",    "Khoisan languages"),
+    lang("km", "khm", "",    "Central Khmer"),
+    lang("",   "kho", "",    "Khotanese; Sakan"),
+    lang("ki", "kik", "",    "Kikuyu; Gikuyu"),
+    lang("rw", "kin", "",    "Kinyarwanda"),
+    lang("ky", "kir", "",    "Kirghiz; Kyrgyz"),
+    lang("",   "kmb", "",    "Kimbundu"),
+    lang("",   "kok", "",    "Konkani"),
+    lang("kv", "kom", "",    "Komi"),
+    lang("kg", "kon", "",    "Kongo"),
+    lang("ko", "kor", "",    "Korean"),
+    lang("",   "kos", "",    "Kosraean"),
+    lang("",   "kpe", "",    "Kpelle"),
+    lang("",   "krc", "",    "Karachay-Balkar"),
+    lang("",   "krl", "",    "Karelian"),
+    lang("",   "kro", "",    "Kru languages"),
+    lang("",   "kru", "",    "Kurukh"),
+    lang("kj", "kua", "",    "Kuanyama; Kwanyama"),
+    lang("",   "kum", "",    "Kumyk"),
+    lang("ku", "kur", "",    "Kurdish"),
+    lang("",   "kut", "",    "Kutenai"),
+    lang("",   "lad", "",    "Ladino"),
+    lang("",   "lah", "",    "Lahnda"),
+    lang("",   "lam", "",    "Lamba"),
+    lang("lo", "lao", "",    "Lao"),
+    lang("la", "lat", "",    "Latin"),
+    lang("lv", "lav", "",    "Latvian"),
+    lang("",   "lez", "",    "Lezghian"),
+    lang("li", "lim", "",    "Limburgan; Limburger; Limburgish"),
+    lang("ln", "lin", "",    "Lingala"),
+    lang("lt", "lit", "",    "Lithuanian"),
+    lang("",   "lol", "",    "Mongo"),
+    lang("",   "loz", "",    "Lozi"),
+    lang("lb", "ltz", "",    "Luxembourgish; Letzeburgesch"),
+    lang("",   "lua", "",    "Luba-Lulua"),
+    lang("lu", "lub", "",    "Luba-Katanga"),
+    lang("lg", "lug", "",    "Ganda"),
+    lang("",   "lui", "",    "Luiseno"),
+    lang("",   "lun", "",    "Lunda"),
+    lang("",   "luo", "",    "Luo (Kenya and Tanzania)"),
+    lang("",   "lus", "",    "Lushai"),
+    lang("",   "mad", "",    "Madurese"),
+    lang("",   "mag", "",    "Magahi"),
+    lang("mh", "mah", "",    "Marshallese"),
+    lang("",   "mai", "",    "Maithili"),
+    lang("",   "mak", "",    "Makasar"),
+    lang("ml", "mal", "",    "Malayalam"),
+    lang("",   "man", "",    "Mandingo"),
+    lang("",   "map", "",    "Austronesian languages"),
+    lang("mr", "mar", "",    "Marathi"),
+    lang("",   "mas", "",    "Masai"),
+    lang("",   "mdf", "",    "Moksha"),
+    lang("",   "mdr", "",    "Mandar"),
+    lang("",   "men", "",    "Mende"),
+    lang("",   "mga", "",    "Irish, Middle (900-1200)"),
+    lang("",   "mic", "",    "Mi'kmaq; Micmac"),
+    lang("",   "min", "",    "Minangkabau"),
+    lang("",   "mis", "",    "Uncoded languages"),
+    lang("mk", "mkd", "mac", "Macedonian"),
+    lang("",   "mkh", "",    "Mon-Khmer languages"),
+    lang("mg", "mlg", "",    "Malagasy"),
+    lang("mt", "mlt", "",    "Maltese"),
+    lang("",   "mnc", "",    "Manchu"),
+    lang("",   "mni", "",    "Manipuri"),
+    lang("",   "mno", "",    "Manobo languages"),
+    lang("",   "moh", "",    "Mohawk"),
+    lang("mn", "mon", "",    "Mongolian"),
+    lang("",   "mos", "",    "Mossi"),
+    lang("mi", "mri", "mao", "Maori"),
+    lang("ms", "msa", "may", "Malay"),
+    lang("",   "mul", "",    "Multiple languages"),
+    lang("",   "mun", "",    "Munda languages"),
+    lang("",   "mus", "",    "Creek"),
+    lang("",   "mwl", "",    "Mirandese"),
+    lang("",   "mwr", "",    "Marwari"),
+    lang("my", "mya", "bur", "Burmese"),
+    lang("",   "myn", "",    "Mayan languages"),
+    lang("",   "myv", "",    "Erzya"),
+    lang("",   "nah", "",    "Nahuatl languages"),
+    lang("",   "nai", "",    "North American Indian languages"),
+    lang("",   "nap", "",    "Neapolitan"),
+    lang("na", "nau", "",    "Nauru"),
+    lang("nv", "nav", "",    "Navajo; Navaho"),
+    lang("nr", "nbl", "",    "South Ndebele"),
+    lang("nd", "nde", "",    "North Ndebele"),
+    lang("ng", "ndo", "",    "Ndonga"),
+    lang("",   "nds", "",    "Low German; Low Saxon"),
+    lang("ne", "nep", "",    "Nepali"),
+    lang("",   "new", "",    "Nepal Bhasa; Newari"),
+    lang("",   "nia", "",    "Nias"),
+    lang("",   "nic", "",    "Niger-Kordofanian languages"),
+    lang("",   "niu", "",    "Niuean"),
+    lang("nl", "nld", "dut", "Dutch; Flemish"),
+    lang("nn", "nno", "",    "Norwegian Nynorsk; Nynorsk"),
+    lang("nb", "nob", "",    "Norwegian Bokmål; Bokmål"),
+    lang("",   "nog", "",    "Nogai"),
+    lang("",   "non", "",    "Norse, Old"),
+    lang("no", "nor", "",    "Norwegian"),
+    lang("",   "nqo", "",    "N'Ko"),
+    lang("",   "nso", "",    "Pedi; Sepedi; Northern Sotho"),
+    lang("",   "nub", "",    "Nubian languages"),
+    lang("",   "nwc", "",    "Classical Newari; Old Newari; Classical Nepal Bhasa"),
+    lang("ny", "nya", "",    "Chichewa; Chewa; Nyanja"),
+    lang("",   "nym", "",    "Nyamwezi"),
+    lang("",   "nyn", "",    "Nyankole"),
+    lang("",   "nyo", "",    "Nyoro"),
+    lang("",   "nzi", "",    "Nzima"),
+    lang("oc", "oci", "",    "Occitan; Provençal"),
+    lang("oj", "oji", "",    "Ojibwa"),
+    lang("or", "ori", "",    "Oriya"),
+    lang("om", "orm", "",    "Oromo"),
+    lang("",   "osa", "",    "Osage"),
+    lang("os", "oss", "",    "Ossetian; Ossetic"),
+    lang("",   "ota", "",    "Turkish, Ottoman (1500-1928)"),
+    lang("",   "oto", "",    "Otomian languages"),
+    lang("",   "paa", "",    "Papuan languages"),
+    lang("",   "pag", "",    "Pangasinan"),
+    lang("",   "pal", "",    "Pahlavi"),
+    lang("",   "pam", "",    "Pampanga; Kapampangan"),
+    lang("pa", "pan", "",    "Panjabi; Punjabi"),
+    lang("",   "pap", "",    "Papiamento"),
+    lang("",   "pau", "",    "Palauan"),
+    lang("",   "peo", "",    "Persian, Old (ca. 600-400 B.C.)"),
+    lang("",   "phi", "",    "Philippine languages"),
+    lang("",   "phn", "",    "Phoenician"),
+    lang("pi", "pli", "",    "Pali"),
+    lang("pl", "pol", "",    "Polish"),
+    lang("",   "pon", "",    "Pohnpeian"),
+    lang("pt", "por", "",    "Portuguese"),
+    lang("",   "pra", "",    "Prakrit languages"),
+    lang("",   "pro", "",    "Provençal, Old (to 1500)"),
+    lang("ps", "pus", "",    "Pushto; Pashto"),
+    lang("qu", "que", "",    "Quechua"),
+    lang("",   "raj", "",    "Rajasthani"),
+    lang("",   "rap", "",    "Rapanui"),
+    lang("",   "rar", "",    "Rarotongan; Cook Islands Maori"),
+    lang("",   "roa", "",    "Romance languages"),
+    lang("rm", "roh", "",    "Romansh"),
+    lang("",   "rom", "",    "Romany"),
+    lang("ro", "ron", "rum", "Romanian; Moldavian; Moldovan"),
+    lang("rn", "run", "",    "Rundi"),
+    lang("",   "rup", "",    "Aromanian; Arumanian; Macedo-Romanian"),
+    lang("ru", "rus", "",    "Russian"),
+    lang("",   "sad", "",    "Sandawe"),
+    lang("sg", "sag", "",    "Sango"),
+    lang("",   "sah", "",    "Yakut"),
+    lang("",   "sai", "",    "South American Indian (Other)"),
+    lang("",   "sal", "",    "Salishan languages"),
+    lang("",   "sam", "",    "Samaritan Aramaic"),
+    lang("sa", "san", "",    "Sanskrit"),
+    lang("",   "sas", "",    "Sasak"),
+    lang("",   "sat", "",    "Santali"),
+    lang("",   "scn", "",    "Sicilian"),
+    lang("",   "sco", "",    "Scots"),
+    lang("",   "sel", "",    "Selkup"),
+    lang("",   "sem", "",    "Semitic languages"),
+    lang("",   "sga", "",    "Irish, Old (to 900)"),
+    lang("",   "sgn", "",    "Sign Languages"),
+    lang("",   "shn", "",    "Shan"),
+    lang("",   "sid", "",    "Sidamo"),
+    lang("si", "sin", "",    "Sinhala; Sinhalese"),
+    lang("",   "sio", "",    "Siouan languages"),
+    lang("",   "sit", "",    "Sino-Tibetan languages"),
+    lang("",   "sla", "",    "Slavic languages"),
+    lang("sk", "slk", "slo", "Slovak"),
+    lang("sl", "slv", "",    "Slovenian"),
+    lang("",   "sma", "",    "Southern Sami"),
+    lang("se", "sme", "",    "Northern Sami"),
+    lang("",   "smi", "",    "Sami languages"),
+    lang("",   "smj", "",    "Lule Sami"),
+    lang("",   "smn", "",    "Inari Sami"),
+    lang("sm", "smo", "",    "Samoan"),
+    lang("",   "sms", "",    "Skolt Sami"),
+    lang("sn", "sna", "",    "Shona"),
+    lang("sd", "snd", "",    "Sindhi"),
+    lang("",   "snk", "",    "Soninke"),
+    lang("",   "sog", "",    "Sogdian"),
+    lang("so", "som", "",    "Somali"),
+    lang("",   "son", "",    "Songhai languages"),
+    lang("st", "sot", "",    "Southern Sotho; Sotho"),
+    lang("es", "spa", "",    "Spanish; Castilian"),
+    lang("sq", "sqi", "alb", "Albanian"),
+    lang("sc", "srd", "",    "Sardinian"),
+    lang("",   "srn", "",    "Sranan Tongo"),
+    lang("sr", "srp", "",    "Serbian"),
+    lang("",   "srr", "",    "Serer"),
+    lang("",   "ssa", "",    "Nilo-Saharan languages"),
+    lang("ss", "ssw", "",    "Swati"),
+    lang("",   "suk", "",    "Sukuma"),
+    lang("su", "sun", "",    "Sundanese"),
+    lang("",   "sus", "",    "Susu"),
+    lang("",   "sux", "",    "Sumerian"),
+    lang("sw", "swa", "",    "Swahili"),
+    lang("sv", "swe", "",    "Swedish"),
+    lang("",   "syc", "",    "Classical Syriac"),
+    lang("",   "syr", "",    "Syriac"),
+    lang("ty", "tah", "",    "Tahitian"),
+    lang("",   "tai", "",    "Tai languages"),
+    lang("ta", "tam", "",    "Tamil"),
+    lang("tt", "tat", "",    "Tatar"),
+    lang("te", "tel", "",    "Telugu"),
+    lang("",   "tem", "",    "Timne"),
+    lang("",   "ter", "",    "Tereno"),
+    lang("",   "tet", "",    "Tetum"),
+    lang("tg", "tgk", "",    "Tajik"),
+    lang("tl", "tgl", "",    "Tagalog"),
+    lang("th", "tha", "",    "Thai"),
+    lang("",   "tig", "",    "Tigre"),
+    lang("ti", "tir", "",    "Tigrinya"),
+    lang("",   "tiv", "",    "Tiv"),
+    lang("",   "tkl", "",    "Tokelau"),
+    lang("",   "tlh", "",    "Klingon; tlhIngan-Hol"),
+    lang("",   "tli", "",    "Tlingit"),
+    lang("",   "tmh", "",    "Tamashek"),
+    lang("",   "tog", "",    "Tonga (Nyasa)"),
+    lang("to", "ton", "",    "Tonga (Tonga Islands)"),
+    lang("",   "tpi", "",    "Tok Pisin"),
+    lang("",   "tsi", "",    "Tsimshian"),
+    lang("tn", "tsn", "",    "Tswana"),
+    lang("ts", "tso", "",    "Tsonga"),
+    lang("tk", "tuk", "",    "Turkmen"),
+    lang("",   "tum", "",    "Tumbuka"),
+    lang("",   "tup", "",    "Tupi languages"),
+    lang("tr", "tur", "",    "Turkish"),
+    lang("",   "tut", "",    "Altaic languages"),
+    lang("",   "tvl", "",    "Tuvalu"),
+    lang("tw", "twi", "",    "Twi"),
+    lang("",   "tyv", "",    "Tuvinian"),
+    lang("",   "udm", "",    "Udmurt"),
+    lang("",   "uga", "",    "Ugaritic"),
+    lang("ug", "uig", "",    "Uighur; Uyghur"),
+    lang("uk", "ukr", "",    "Ukrainian"),
+    lang("",   "umb", "",    "Umbundu"),
+    lang("",   "und", "",    "Undetermined"),
+    lang("ur", "urd", "",    "Urdu"),
+    lang("uz", "uzb", "",    "Uzbek"),
+    lang("",   "vai", "",    "Vai"),
+    lang("ve", "ven", "",    "Venda"),
+    lang("vi", "vie", "",    "Vietnamese"),
+    lang("vo", "vol", "",    "Volapük"),
+    lang("",   "vot", "",    "Votic"),
+    lang("",   "wak", "",    "Wakashan languages"),
+    lang("",   "wal", "",    "Walamo"),
+    lang("",   "war", "",    "Waray"),
+    lang("",   "was", "",    "Washo"),
+    lang("",   "wen", "",    "Sorbian languages"),
+    lang("wa", "wln", "",    "Walloon"),
+    lang("wo", "wol", "",    "Wolof"),
+    lang("",   "xal", "",    "Kalmyk; Oirat"),
+    lang("xh", "xho", "",    "Xhosa"),
+    lang("",   "yao", "",    "Yao"),
+    lang("",   "yap", "",    "Yapese"),
+    lang("yi", "yid", "",    "Yiddish"),
+    lang("yo", "yor", "",    "Yoruba"),
+    lang("",   "ypk", "",    "Yupik languages"),
+    lang("",   "zap", "",    "Zapotec"),
+    lang("",   "zbl", "",    "Blissymbols; Blissymbolics; Bliss"),
+    lang("",   "zen", "",    "Zenaga"),
+    lang("",   "zgh", "",    "Standard Moroccan Tamazight"),
+    lang("za", "zha", "",    "Zhuang; Chuang"),
+    lang("zh", "zho", "chi", "Chinese"),
+    lang("",   "znd", "",    "Zande languages"),
+    lang("zu", "zul", "",    "Zulu"),
+    lang("",   "zun", "",    "Zuni"),
+    lang("",   "zxx", "",    "No linguistic content"),
+    lang("",   "zza", "",    "Zaza; Dimili; Dimli; Kirdki; Kirmanjki; Zazaki"),
+];
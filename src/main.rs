@@ -11,10 +11,22 @@ use std::{
 
 mod r#async;
 mod command;
+mod conversion;
+mod crf_search;
+mod db;
+mod detect;
 mod directory;
+mod episodes;
+mod history;
 mod input;
 mod interface;
+mod interlace;
+mod lang;
+mod movie;
+mod overrides;
+mod quality;
 mod state;
+mod template;
 mod tv;
 mod util;
 
@@ -22,25 +34,34 @@ use clap::Parser;
 use color_eyre::eyre::{Context, Result, eyre};
 use colored::Colorize;
 use ffmpeg::ChannelLayout;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
 use question::Answer;
-use regex::Regex;
 use tokio::runtime::Runtime;
 use tracing::*;
 use tracing_subscriber::EnvFilter;
-use tv::TVOptions;
+use tv::{FileEpisode, ParsedEpisode, Seasons, TVOptions};
 use walkdir::WalkDir;
 
-use crate::{command::CommandError, directory::OutputDir, input::Stream, state::Db};
+use crate::{
+    command::{CommandError, EncodeOptions, VideoFilters},
+    conversion::Analysis,
+    directory::OutputDir,
+    input::{DynamicRange, Stream},
+    interface::Subcommand,
+    movie::Movie,
+    overrides::{OverrideConfig, Overrides},
+    state::Db,
+};
 
 static ARGS: Lazy<interface::Args> = Lazy::new(interface::Args::parse);
-static TV_INFORMATION_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"[sS](\d\d)\s*[eE](\d\d)").unwrap());
 
 const EXEMPT_FILE_EXTENSIONS: [&str; 12] = [
     "clbin", "gif", "jpg", "md", "nfo", "png", "py", "rar", "sfv", "srr", "txt", "srt",
 ];
 const SUBTITLE_EXTS: [&str; 2] = ["ass", "srt"];
+/// The directory `--preview` clips are written to, relative to the working directory
+const PREVIEW_DIR: &str = "previews";
 
 fn main() -> Result<()> {
     color_eyre::install()?;
@@ -54,7 +75,7 @@ fn main() -> Result<()> {
 
     debug!(?ARGS);
 
-    let db = Db::new().unwrap();
+    let db = Db::new().map_err(|e| eyre!("Failed to open the state database: {e}"))?;
 
     // Shut libav* up
     // Safety: No other threads exist, mutating global state is fine.
@@ -62,11 +83,12 @@ fn main() -> Result<()> {
         ffmpeg::ffi::av_log_set_level(ffmpeg::ffi::AV_LOG_FATAL);
     }
 
-    if ARGS.dump_db {
-        return db.dump();
-    }
-    if let Some(title) = &ARGS.remove_db_entry {
-        return db.remove_entry(title);
+    if let Some(command) = &ARGS.command {
+        return match command {
+            Subcommand::Db(command) => db::run(command, &db),
+            Subcommand::Episodes(command) => episodes::run(command, &db),
+            Subcommand::History { show, verbose } => history::run(show.as_deref(), *verbose, &db),
+        };
     }
 
     let entries = {
@@ -148,41 +170,22 @@ fn main() -> Result<()> {
         .iter()
         .enumerate()
         .filter_map(|(i, entry)| {
-            let c = TV_INFORMATION_REGEX.captures(entry.to_str()?)?;
-            let season = c
-                .get(1)?
-                .as_str()
-                .parse()
-                .expect("2 digits in a row should be parseable");
-            let episode = c
-                .get(2)?
-                .as_str()
-                .parse()
-                .expect("2 digits in a row should be parseable");
-            Some((i, (season, episode)))
+            let parsed = ParsedEpisode::parse(&entry.file_name()?.to_string_lossy())?;
+            trace!(?entry, ?parsed, "Parsed episode from filename");
+            Some((i, parsed))
         })
         .collect();
 
     let mut tv_options = TVOptions::from_cli(&db, title.as_deref(), &filename_information);
-    let rename_title = if tv_options.is_none() && entries.len() == 1 {
-        let rename = util::confirm("Rename the file? (disables folder creation)", None);
-        if rename {
-            let mut title;
-            Some(loop {
-                title = util::prompt("Title to rename to:");
-                if !title.is_empty() {
-                    break title;
-                }
-            })
-        } else {
-            None
-        }
+    let movies = if tv_options.is_none() {
+        Movie::from_cli(&entries)
     } else {
         None
     };
 
     if let Some(ref tv_options) = tv_options {
-        db.write(tv_options);
+        db.write(tv_options)
+            .wrap_err("Failed to save the TV show to the state database")?;
     }
 
     debug!(?tv_options);
@@ -211,10 +214,18 @@ fn main() -> Result<()> {
     }
 
     let mut commands = Vec::with_capacity(entries.len());
+    let mut previews = Vec::new();
+    let threads = r#async::threads_per_job(entries.len());
+
+    let seasons = tv_options
+        .as_ref()
+        .map(|x| Seasons::load(&db, &x.title))
+        .unwrap_or_default();
 
-    let output_dir = OutputDir::new(&tv_options, &rename_title);
+    let output_dir = OutputDir::new(&tv_options, movies.is_some());
 
     let mut errored_paths = Vec::new();
+    let mut last_processed = None;
 
     println!(
         "{}{} {}{}",
@@ -224,32 +235,109 @@ fn main() -> Result<()> {
         ": path althready exists".dimmed()
     );
 
+    let rt = Runtime::new()?;
+
+    let override_config = ARGS
+        .overrides
+        .as_deref()
+        .map(OverrideConfig::load)
+        .transpose()?;
+
     for (i, input_filepath) in entries.iter().enumerate() {
+        let mut overrides = overrides::for_file(input_filepath, override_config.as_ref())?;
+        debug!(?overrides);
+
+        if let Some(ref mut tv_options) = tv_options
+            && let Some(episode) = overrides.episode
+        {
+            tv_options.episode = episode;
+        }
+
         let associated_subs = associated_subtitles
             .get(input_filepath.as_path())
             .map(|v| v.as_slice())
             .unwrap_or(&[]);
 
-        let output_filename =
-            command::generate_output_filename(input_filepath, &tv_options, rename_title.as_deref());
-        let output_path = output_dir.0.join(output_filename);
+        let file_episode = tv_options.as_ref().map(|tv_options| {
+            let parsed = filename_information.get(&i);
+            let count = parsed.map(ParsedEpisode::count).unwrap_or(1);
+            // Absolutely numbered files are placed by their own number, which may be in a
+            // different season to the one being counted through, as are all files in a batch
+            // spanning several seasons
+            let (season, episodes) = match parsed {
+                Some(parsed @ ParsedEpisode::Absolute(_)) => parsed.resolve(&db, &tv_options.title),
+                Some(parsed) if tv_options.from_filenames => parsed.resolve(&db, &tv_options.title),
+                _ => None,
+            }
+            .unwrap_or_else(|| {
+                let (season, episode) = seasons.wrap(tv_options.season, tv_options.episode);
+                // As with absolute numbers, a file straddling the end of a season is named after
+                // the episodes in that season
+                let last = match seasons.length(season) {
+                    Some(length) => (episode + count - 1).min(length).max(episode),
+                    None => episode + count - 1,
+                };
+                (season, episode..=last)
+            });
+            FileEpisode {
+                season,
+                episode: *episodes.start(),
+                last_episode: (episodes.end() > episodes.start()).then_some(*episodes.end()),
+            }
+        });
+        let episode_title =
+            tv_options
+                .as_ref()
+                .zip(file_episode)
+                .and_then(|(tv_options, file_episode)| {
+                    episodes::title(&db, &tv_options.title, file_episode)
+                });
+
+        let output_path = match output_dir.output_path(
+            input_filepath,
+            tv_options
+                .as_ref()
+                .map(|x| x.title.as_str())
+                .zip(file_episode),
+            movies.as_ref().map(|x| &x[i]),
+            episode_title.as_deref(),
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                error!(file = ?input_filepath, "Failed to generate output path: {e}");
+                if !ARGS.continue_processing {
+                    std::process::exit(1);
+                }
+                errored_paths.push(input_filepath);
+                continue;
+            }
+        };
 
-        if let Some(ref mut tv_options) = tv_options {
-            if let Some((season, episode)) = filename_information.get(&i) {
-                if tv_options.episode != *episode {
+        if let Some(ref mut tv_options) = tv_options
+            && let Some(file_episode) = file_episode
+        {
+            if let Some((season, episodes)) = filename_information
+                .get(&i)
+                .and_then(|x| x.resolve(&db, &tv_options.title))
+            {
+                if file_episode.episode != *episodes.start() {
                     warn!(
                         "Episode count mismatch detected! ({} != {})",
-                        tv_options.episode, episode
+                        file_episode.episode,
+                        episodes.start()
                     );
                 }
-                if tv_options.season != *season {
+                if file_episode.season != season {
                     warn!(
                         "Season number mismatch detected! ({} != {})",
-                        tv_options.episode, episode
+                        file_episode.season, season
                     );
                 }
             }
-            tv_options.episode += 1;
+            let last = file_episode.last_episode.unwrap_or(file_episode.episode);
+            last_processed = Some((file_episode.season, last));
+            tv_options.season = file_episode.season;
+            tv_options.episode = last + 1;
         }
 
         let file = ffmpeg::format::input(&input_filepath)
@@ -263,17 +351,92 @@ fn main() -> Result<()> {
             parsed.extend_from_slice(&input::parse_stream_metadata(file, i + 1));
         }
 
-        let stream_mappings = input::get_stream_mappings(&parsed);
-        let codec_mappings = input::get_codec_mapping(&stream_mappings);
+        if ARGS.detect_language {
+            let paths = iter::once(input_filepath.as_path())
+                .chain(associated_subs.iter().map(|x| x.as_path()))
+                .collect::<Vec<_>>();
+            detect::infer_languages(&mut parsed, &paths);
+        }
 
-        let mappings = &stream_mappings;
-        let codecs = &codec_mappings;
+        for stream in parsed.iter_mut() {
+            if let Stream::Video(video) = stream
+                && video.file == 0
+            {
+                video.deinterlace_override = overrides.deinterlace;
+            }
+        }
+
+        let mut stream_mappings =
+            match input::get_stream_mappings(&parsed, input_filepath, &overrides) {
+                Ok(stream_mappings) => stream_mappings,
+                Err(e) => {
+                    error!("{e}");
+                    if !ARGS.continue_processing {
+                        std::process::exit(1);
+                    }
+                    errored_paths.push(input_filepath);
+                    continue;
+                }
+            };
 
-        if mappings.video.is_empty() {
+        if stream_mappings.video.is_empty() {
             error!("No video streams found");
             std::process::exit(1);
         }
 
+        let inputs = iter::once(input_filepath.as_path())
+            .chain(associated_subs.iter().map(|x| x.as_path()))
+            .collect::<Vec<_>>();
+        let length = input::length(input_filepath);
+        let fingerprint = util::fingerprint(input_filepath, length)
+            .inspect_err(|e| warn!(file = ?input_filepath, err = %e, "Failed to fingerprint input"))
+            .ok();
+
+        // Skip inputs already converted with the same settings, as long as the output hasn't been
+        // deleted since. The analysis stored with previous conversions is reused, so skipped
+        // inputs aren't analysed again.
+        if !ARGS.force
+            && let Some(fingerprint) = &fingerprint
+            && let Some(output) = conversion::previous(
+                &db,
+                &fingerprint.to_string(),
+                &stream_mappings,
+                &overrides,
+                |mappings, overrides| {
+                    let codecs = input::get_codec_mapping(&mappings);
+                    let command = command::generate_ffmpeg_command(
+                        input_filepath.as_path(),
+                        associated_subs,
+                        &output_path,
+                        mappings,
+                        codecs,
+                        EncodeOptions {
+                            overrides,
+                            threads,
+                            segment: None,
+                            title: episode_title.as_deref(),
+                        },
+                    )
+                    .ok()?;
+                    Some(command::conversion_key(&command, &inputs, &output_path))
+                },
+            )
+        {
+            println!(
+                "Skipping {}: already converted to {output}",
+                input_filepath.display()
+            );
+            continue;
+        }
+
+        if ARGS.detect_interlacing {
+            interlace::analyse(&mut stream_mappings.video, input_filepath, &db);
+        }
+        let codec_mappings = input::get_codec_mapping(&stream_mappings);
+
+        let mappings = &stream_mappings;
+        let codecs = &codec_mappings;
+
         print!("Input file '{}' -> ", input_filepath.display());
 
         // We print the destination path step-by-step to tell the user which directories in the
@@ -297,6 +460,12 @@ fn main() -> Result<()> {
                 print!("'{}' ", title.unwrap_or("[untitled]"));
             }
 
+            if stream.lang_inferred()
+                && let Some(lang) = stream.lang()
+            {
+                print!("[{lang}, inferred] ");
+            }
+
             if let Stream::Audio(audio) = stream {
                 if audio.channel_layout == ChannelLayout::STEREO {
                     print!("(2.0) ");
@@ -321,26 +490,73 @@ fn main() -> Result<()> {
                 print!("(copy) ")
             }
 
-            if matches!(stream, input::Stream::Video(_)) && codec.is_some() {
-                let crop = ARGS.crop.is_some();
-                // FIXME: fails to specify deinterlacing in log message if the deinterlacing is
-                // inferred from the video stream.
-                let deinterlace = ARGS.force_deinterlace;
-                if crop || deinterlace {
-                    print!("(");
-                    if crop {
-                        print!("crop")
-                    }
-                    if crop && deinterlace {
-                        print!(", deinterlace")
-                    } else if deinterlace {
-                        print!("deinterlace")
+            if let Some(reason) = mappings.video_reasons.get(&(file, index)) {
+                print!("[{reason}] ");
+            }
+
+            if let Stream::Video(video) = stream
+                && !video.is_cover_art
+            {
+                match video.colour.dynamic_range() {
+                    DynamicRange::Sdr if video.colour.bit_depth > 8 => {
+                        print!("({}-bit) ", video.colour.bit_depth)
                     }
-                    print!(")")
+                    DynamicRange::Sdr => {}
+                    DynamicRange::DolbyVision => print!(
+                        "(Dolby Vision profile {}) ",
+                        video
+                            .colour
+                            .dolby_vision
+                            .map(|x| x.profile)
+                            .unwrap_or_default()
+                    ),
+                    range => print!("({range}) "),
+                }
+                if video.is_vfr {
+                    print!("(VFR) ");
+                }
+            }
+
+            if let Stream::Video(video) = stream
+                && codec.is_some()
+            {
+                let mut filters = Vec::new();
+                if overrides.crop.is_some() || ARGS.crop.is_some() {
+                    filters.push("crop".to_owned());
+                }
+                if let Some(deinterlace) = video.deinterlace() {
+                    let mode = match deinterlace.mode {
+                        input::DeinterlaceMode::Deinterlace => "deinterlace",
+                        input::DeinterlaceMode::Ivtc => "ivtc",
+                    };
+                    let source = match (deinterlace.inferred, video.scan_type) {
+                        (false, _) => "",
+                        (true, Some(_)) => ", inferred from analysis",
+                        (true, None) => ", inferred from field order",
+                    };
+                    filters.push(format!("{mode}: {}{source}", ARGS.deinterlacer));
+                }
+                if let Some(scale) = video.scale() {
+                    filters.push(format!("scale: {}p", scale.height()));
+                }
+                if let Some(tonemap) = video.tonemap() {
+                    filters.push(format!("tonemap: {tonemap}"));
+                }
+                if !filters.is_empty() {
+                    print!("({})", filters.join(", "));
                 }
             }
             println!();
         }
+        for stream in &mappings.dropped_video {
+            let (file, index) = (stream.file(), stream.index());
+            print!("Dropping stream {file}:{index}: {:?}", stream.codec());
+            if let Some(reason) = mappings.video_reasons.get(&(file, index)) {
+                print!(" [{reason}]");
+            }
+            println!();
+        }
+
         let dropped_audio =
             parsed.iter().filter(|&x| x.as_audio().is_some()).count() - mappings.audio.len();
         let dropped_subs =
@@ -349,38 +565,190 @@ fn main() -> Result<()> {
             println!("Dropping {dropped_audio} audio streams and {dropped_subs} subtitle streams",);
         }
 
+        let Some(Stream::Video(main_video)) = mappings.video.first() else {
+            unreachable!("mappings.video was checked to be non-empty");
+        };
+        let reencoding_video = codecs[&main_video.index].is_some() || ARGS.reencode_video;
+        let filters = VideoFilters::new(main_video, &overrides, threads);
+        let mut job = Job {
+            input: input_filepath.clone(),
+            filename: output_path,
+            length,
+            video_index: main_video.index,
+            reference_filter: filters.reference_chain().filter(|_| reencoding_video),
+            settings: String::new(),
+            conversion_key: String::new(),
+            show: tv_options.as_ref().map(|x| x.title.clone()),
+            episode: file_episode,
+            fingerprint,
+            analysis: Analysis::default(),
+        };
+
+        // A CRF in the per-file overrides takes precedence over the search
+        let mut searched_crf = None;
+        if let Some(target) = ARGS.target_vmaf
+            && reencoding_video
+            && overrides.crf.is_none()
+        {
+            let encode = |crf, segment, output: &Path| {
+                let overrides = Overrides {
+                    crf: Some(crf),
+                    ..overrides.clone()
+                };
+                command::generate_ffmpeg_command(
+                    input_filepath.as_path(),
+                    associated_subs,
+                    output,
+                    stream_mappings.clone(),
+                    codec_mappings.clone(),
+                    EncodeOptions {
+                        overrides: &overrides,
+                        threads,
+                        segment: Some(segment),
+                        title: episode_title.as_deref(),
+                    },
+                )
+            };
+            match crf_search::search(&job, filters.chain().as_deref(), target, &db, &rt, encode) {
+                Ok(search) => {
+                    println!("{search}");
+                    overrides.crf = Some(search.crf);
+                    searched_crf = Some(search.crf);
+                }
+                Err(e) => {
+                    error!(file = ?input_filepath, "CRF search failed: {e}");
+                    if !ARGS.continue_processing {
+                        std::process::exit(1);
+                    }
+                    errored_paths.push(input_filepath);
+                    continue;
+                }
+            }
+        }
+
+        job.analysis = Analysis::new(mappings, searched_crf);
+
         let command = command::generate_ffmpeg_command(
-            input_filepath,
+            input_filepath.as_path(),
             associated_subs,
-            &output_path,
-            stream_mappings,
-            codec_mappings,
+            &job.filename,
+            stream_mappings.clone(),
+            codec_mappings.clone(),
+            EncodeOptions {
+                overrides: &overrides,
+                threads,
+                segment: None,
+                title: episode_title.as_deref(),
+            },
         );
-        let length = input::length(input_filepath);
 
         info!(?command);
-        match command {
-            Ok(command) => commands.push(Command {
-                inner: command,
-                length,
-                filename: output_path,
-            }),
-            Err(CommandError::FileExists) => {
+        let command = match command {
+            Ok(command) => {
+                let settings = command::settings(&command, &inputs, &job.filename);
+                let conversion_key = command::conversion_key(&command, &inputs, &job.filename);
+
+                // Inputs converted before analyses were stored with conversions are only
+                // recognised now
+                if !ARGS.force
+                    && let Some(fingerprint) = &job.fingerprint
+                {
+                    match db.conversion(&fingerprint.to_string(), &conversion_key) {
+                        Ok(Some(output)) if Path::new(&output).exists() => {
+                            println!(
+                                "Skipping {}: already converted to {output}",
+                                input_filepath.display()
+                            );
+                            continue;
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!(file = ?input_filepath, err = %e, "Failed to look up conversions")
+                        }
+                    }
+                }
+                command::check_output(&job.filename).map(|()| (command, settings, conversion_key))
+            }
+            Err(e) => Err(e),
+        };
+        let (command, settings, conversion_key) = match command {
+            Ok(command) => command,
+            Err(e) => {
+                // The file exists error has already been reported when prompting to overwrite
+                if !matches!(e, CommandError::FileExists) {
+                    error!(file = ?input_filepath, "{e}");
+                }
                 if !ARGS.continue_processing {
                     std::process::exit(1);
                 }
                 errored_paths.push(input_filepath);
                 continue;
             }
+        };
+
+        // The preview is encoded with exactly the same arguments as the full file, just limited to
+        // a segment of it
+        if let Some(preview) = ARGS.preview
+            && (i == 0 || ARGS.preview_all)
+        {
+            let segment = preview.segment(job.length);
+            // Outputs in a batch may share a filename in different show or season directories, so
+            // their directories are kept
+            let relative = job
+                .filename
+                .strip_prefix(&output_dir.0)
+                .unwrap_or_else(|_| Path::new(job.filename.file_name().unwrap_or_default()));
+            let filename = Path::new(PREVIEW_DIR).join(relative);
+            let command = command::generate_ffmpeg_command(
+                input_filepath.as_path(),
+                associated_subs,
+                &filename,
+                stream_mappings,
+                codec_mappings,
+                EncodeOptions {
+                    overrides: &overrides,
+                    threads,
+                    segment: Some(segment),
+                    title: episode_title.as_deref(),
+                },
+            );
+            match command {
+                Ok(command) => previews.push(Command {
+                    inner: command,
+                    job: Job {
+                        filename,
+                        length: segment.length,
+                        ..job.clone()
+                    },
+                }),
+                Err(e) => error!(file = ?input_filepath, "Failed to generate preview command: {e}"),
+            }
         }
+
+        commands.push(Command {
+            inner: command,
+            job: Job {
+                settings,
+                conversion_key,
+                ..job
+            },
+        });
     }
 
-    if let Some(TVOptions { title, episode, .. }) = tv_options {
-        db.update_episode(&title, episode - 1);
+    // Record the last episode processed, so the next batch can carry on from it
+    if let Some(tv_options) = tv_options
+        && let Some((season, episode)) = last_processed
+    {
+        db.write(&TVOptions {
+            season,
+            episode,
+            ..tv_options
+        })
+        .wrap_err("Failed to save the last episode processed to the state database")?;
     }
 
     if ARGS.print_commands {
-        for command in &commands {
+        for command in previews.iter().chain(&commands) {
             let command = command.inner.as_std();
             let cmd = iter::once(command.get_program())
                 .chain(command.get_args())
@@ -398,15 +766,45 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    if !previews.is_empty() {
+        for dir in previews
+            .iter()
+            .filter_map(|x| x.job.filename.parent())
+            .unique()
+        {
+            std::fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let finished = rt.block_on(r#async::run_commands(previews))?;
+        println!("Previews:");
+        for (job, outcome) in &finished {
+            if outcome.status.success() {
+                println!("  {}", job.filename.display());
+            } else {
+                println!("  {} ({})", job.filename.display(), outcome.status);
+            }
+        }
+    }
+
     if ARGS.yes || !util::confirm("Continue?", Some(Answer::YES)) {
         eprintln!("Aborting");
         return Ok(());
     }
 
-    output_dir.create().unwrap();
+    for dir in commands
+        .iter()
+        .filter_map(|x| x.job.filename.parent())
+        .unique()
+    {
+        directory::create(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+    }
 
-    let rt = Runtime::new()?;
-    rt.block_on(r#async::run_commands(commands))?;
+    let finished = rt.block_on(r#async::run_commands(commands))?;
+    history::record(&finished, &db);
+
+    if !ARGS.quality_metrics.is_empty() {
+        quality::evaluate(&finished, &db);
+    }
 
     if !errored_paths.is_empty() {
         eprintln!("Errors occured in {} paths:", errored_paths.len());
@@ -420,8 +818,30 @@ fn main() -> Result<()> {
 
 struct Command {
     inner: tokio::process::Command,
-    length: Duration,
+    job: Job,
+}
+
+/// What a command does, kept after it has run
+#[derive(Debug, Clone)]
+struct Job {
+    input: PathBuf,
     filename: PathBuf,
+    length: Duration,
+    /// The index of the main video stream in the input
+    video_index: usize,
+    /// The filters to apply to the input's video to compare it with the output
+    reference_filter: Option<String>,
+    /// The ffmpeg arguments, without the input and output paths. Set once the command is generated
+    settings: String,
+    /// See `command::conversion_key`. Set with `settings`
+    conversion_key: String,
+    /// The TV show and episode(s) the output is, in TV mode
+    show: Option<String>,
+    episode: Option<FileEpisode>,
+    /// `None` if the input couldn't be read
+    fingerprint: Option<util::Fingerprint>,
+    /// Stored with the conversion, so the input can be skipped without analysing it again
+    analysis: Analysis,
 }
 
 fn print_path_colourised(output_path: &Path) {
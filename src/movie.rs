@@ -0,0 +1,211 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use question::Answer;
+use regex::Regex;
+use tracing::*;
+
+use crate::{ARGS, util};
+
+static YEAR_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[(\[]?((?:19|20)\d\d)[)\]]?$").unwrap());
+/// Resolutions, sources, codecs and release tags that follow the title and year in scene names
+static NOISE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?ix)^(
+            \d{3,4}[pi] | 4k | uhd | hdr | hdr10\+? | dv | sdr | 10bit | 8bit
+            | blu-?ray | bdrip | brrip | bd | remux | web-?dl | web-?rip | web | hdtv | dvd-?rip
+            | dvd(?:5|9)? | hdrip
+            | [xh]\.?26[45] | hevc | avc | xvid | divx
+            | aac | ac3 | e?ac-?3 | dts(?:-?(?:hd|ma|x))? | ddp?(?:\d\.\d)? | truehd | atmos | flac
+            | proper | repack | extended | unrated | remastered | uncut | limited | internal | imax
+            | multi | subbed | dubbed
+        )$",
+    )
+    .unwrap()
+});
+static BRACKETS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[^\]]*\]").unwrap());
+
+/// A movie's title and release year, used to name its output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub title: String,
+    pub year: Option<u32>,
+}
+
+impl Display for Movie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.year {
+            Some(year) => write!(f, "{} ({year})", self.title),
+            None => f.write_str(&self.title),
+        }
+    }
+}
+
+impl Movie {
+    /// Parses a scene-style name like `Movie.Name.2019.1080p.BluRay.x264-GRP` into the title and
+    /// year. Everything from the last year on, or from the first resolution, source or codec tag
+    /// if there's no year, is dropped. Tags are only looked for without a year, as titles can
+    /// contain words like `Web` or `Uncut`.
+    pub fn parse(name: &str) -> Option<Self> {
+        // Bracketed tags like `[YTS.MX]` are dropped, unless they hold the year
+        let name = BRACKETS_REGEX.replace_all(name, |c: &regex::Captures| {
+            if YEAR_REGEX.is_match(&c[0]) {
+                format!(" {} ", &c[0])
+            } else {
+                " ".to_owned()
+            }
+        });
+        let tokens = name
+            .split(['.', '_', ' '])
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>();
+
+        // The first token is always part of the title, e.g. `2001.A.Space.Odyssey.1968`
+        let is_noise = |token: &str| {
+            // A release group is attached to the last tag, e.g. `x264-GRP`
+            let tag = token.rsplit_once('-').map(|(x, _)| x).unwrap_or(token);
+            NOISE_REGEX.is_match(token) || NOISE_REGEX.is_match(tag)
+        };
+        let year_index = (1..tokens.len())
+            .rev()
+            .find(|&i| YEAR_REGEX.is_match(tokens[i]));
+        let end = year_index.unwrap_or_else(|| {
+            (1..tokens.len())
+                .find(|&i| is_noise(tokens[i]))
+                .unwrap_or(tokens.len())
+        });
+
+        let title = tokens[..end].join(" ");
+        let title = title.trim_matches(|c: char| c == '-' || c.is_whitespace());
+        if title.is_empty() {
+            return None;
+        }
+        Some(Movie {
+            title: title.to_owned(),
+            year: year_index.and_then(|i| YEAR_REGEX.captures(tokens[i])?[1].parse().ok()),
+        })
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        Movie::parse(&path.file_stem()?.to_string_lossy())
+    }
+
+    /// Finds the title and year of each input in movie mode, confirming each guess unless
+    /// `--movie-mode` was passed. Returns `None` if movie mode isn't enabled.
+    pub fn from_cli(entries: &[PathBuf]) -> Option<Vec<Movie>> {
+        if !ARGS.movie_mode && !util::confirm("Movie Mode", Some(Answer::NO)) {
+            return None;
+        }
+
+        Some(
+            entries
+                .iter()
+                .map(|path| {
+                    let detected = Movie::from_path(path);
+                    trace!(?path, ?detected, "Parsed movie title");
+                    if ARGS.movie_mode
+                        && let Some(detected) = detected
+                    {
+                        if detected.year.is_none() {
+                            warn!(file = ?path, title = %detected, "No year found in filename");
+                        }
+                        return detected;
+                    }
+
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    if let Some(detected) = detected
+                        && util::confirm(
+                            &format!("Use detected title for {name}? ({detected})"),
+                            Some(Answer::YES),
+                        )
+                    {
+                        return detected;
+                    }
+                    loop {
+                        let response = util::prompt(&format!(
+                            "Please enter the title of {name}, e.g. `Movie Name (2019)`:"
+                        ));
+                        if let Some(movie) = Movie::parse(&response) {
+                            break movie;
+                        }
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> (String, Option<u32>) {
+        let movie = Movie::parse(name).unwrap();
+        (movie.title, movie.year)
+    }
+
+    #[test]
+    fn scene_names() {
+        assert_eq!(
+            parse("Movie.Name.2019.1080p.BluRay.x264-GRP"),
+            ("Movie Name".to_owned(), Some(2019))
+        );
+        assert_eq!(
+            parse("Spider-Man.No.Way.Home.2021.WEB-DL.DDP5.1.x265-GRP"),
+            ("Spider-Man No Way Home".to_owned(), Some(2021))
+        );
+        assert_eq!(
+            parse("The.Matrix.1999"),
+            ("The Matrix".to_owned(), Some(1999))
+        );
+    }
+
+    #[test]
+    fn bracketed_tags_and_years() {
+        assert_eq!(
+            parse("Blade Runner 2049 (2017) [1080p] [YTS.MX]"),
+            ("Blade Runner 2049".to_owned(), Some(2017))
+        );
+        assert_eq!(
+            parse("[Group] Akira (1988) [BD 1080p]"),
+            ("Akira".to_owned(), Some(1988))
+        );
+        assert_eq!(
+            parse("Movie Name (2019)"),
+            ("Movie Name".to_owned(), Some(2019))
+        );
+    }
+
+    #[test]
+    fn titles_starting_with_a_year() {
+        assert_eq!(
+            parse("2001.A.Space.Odyssey.1968.2160p.UHD"),
+            ("2001 A Space Odyssey".to_owned(), Some(1968))
+        );
+        assert_eq!(parse("1917.2019.1080p"), ("1917".to_owned(), Some(2019)));
+    }
+
+    #[test]
+    fn titles_containing_tags() {
+        assert_eq!(
+            parse("Charlotte's.Web.2006.1080p"),
+            ("Charlotte's Web".to_owned(), Some(2006))
+        );
+        assert_eq!(
+            parse("The.Uncut.Gems.2019.WEB-DL"),
+            ("The Uncut Gems".to_owned(), Some(2019))
+        );
+        assert_eq!(
+            parse("Multi.Dimensional.2010.BD.1080p"),
+            ("Multi Dimensional".to_owned(), Some(2010))
+        );
+    }
+
+    #[test]
+    fn no_year() {
+        assert_eq!(parse("Heat.1080p.BluRay"), ("Heat".to_owned(), None));
+        assert_eq!(parse("Some Film"), ("Some Film".to_owned(), None));
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use color_eyre::eyre::{Result, WrapErr};
+use glob::Pattern;
+use serde::{Deserialize, Deserializer, de};
+use tracing::*;
+
+use crate::interface::{CropFilter, StreamRef};
+
+/// The name of the overrides file read from each input's directory
+pub const SIDECAR_FILENAME: &str = ".videoconverter.toml";
+
+/// Settings that can be changed for individual files in a batch. Each field replaces the
+/// corresponding command-line option.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Overrides {
+    #[serde(deserialize_with = "parse")]
+    pub crop: Option<CropFilter>,
+    pub deinterlace: Option<DeinterlaceOverride>,
+    pub crf: Option<u8>,
+    #[serde(deserialize_with = "parse_all")]
+    pub override_audio: Option<Vec<StreamRef>>,
+    #[serde(deserialize_with = "parse_all")]
+    pub override_subs: Option<Vec<StreamRef>>,
+    /// The episode number to use in TV mode. Following files are numbered on from this one
+    pub episode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeinterlaceOverride {
+    /// Deinterlace if the video is detected or flagged as interlaced
+    Auto,
+    /// Always deinterlace
+    Force,
+    /// Inverse telecine
+    Ivtc,
+    /// Never deinterlace
+    None,
+}
+
+impl Overrides {
+    /// Fills any unset fields from `other`
+    fn or(self, other: &Overrides) -> Overrides {
+        Overrides {
+            crop: self.crop.or_else(|| other.crop.clone()),
+            deinterlace: self.deinterlace.or(other.deinterlace),
+            crf: self.crf.or(other.crf),
+            override_audio: self.override_audio.or_else(|| other.override_audio.clone()),
+            override_subs: self.override_subs.or_else(|| other.override_subs.clone()),
+            episode: self.episode.or(other.episode),
+        }
+    }
+}
+
+/// A set of overrides keyed by glob patterns, e.g.
+///
+/// ```toml
+/// ["*S01E13*"]
+/// deinterlace = "force"
+/// crf = 18
+/// ```
+///
+/// Patterns are matched against both the full path and the filename of each input.
+#[derive(Debug, Default)]
+pub struct OverrideConfig(Vec<(Pattern, Overrides)>);
+
+impl OverrideConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read overrides from {}", path.display()))?;
+        let sections: BTreeMap<String, Overrides> = toml::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse overrides in {}", path.display()))?;
+
+        let mut sections = sections
+            .into_iter()
+            .map(|(pattern, overrides)| {
+                let pattern = Pattern::new(&pattern).wrap_err_with(|| {
+                    format!("Invalid pattern '{pattern}' in {}", path.display())
+                })?;
+                Ok((pattern, overrides))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // If several sections match a file, longer (more specific) patterns take precedence
+        sections.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.as_str().len()));
+        Ok(Self(sections))
+    }
+
+    /// Merges the overrides of every section matching `path`
+    pub fn for_file(&self, path: &Path) -> Overrides {
+        let filename = path.file_name().map(Path::new);
+        self.0
+            .iter()
+            .filter(|(pattern, _)| {
+                pattern.matches_path(path) || filename.is_some_and(|x| pattern.matches_path(x))
+            })
+            .inspect(|(pattern, _)| trace!(path = ?path, %pattern, "Override section matches"))
+            .fold(Overrides::default(), |acc, (_, overrides)| {
+                acc.or(overrides)
+            })
+    }
+}
+
+/// Finds the overrides for `path` from the sidecar file in its directory, then from `config`.
+pub fn for_file(path: &Path, config: Option<&OverrideConfig>) -> Result<Overrides> {
+    let sidecar = match path.parent().map(|x| x.join(SIDECAR_FILENAME)) {
+        Some(sidecar) if sidecar.is_file() => OverrideConfig::load(&sidecar)?.for_file(path),
+        _ => Overrides::default(),
+    };
+    Ok(match config {
+        Some(config) => sidecar.or(&config.for_file(path)),
+        None => sidecar,
+    })
+}
+
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|x| x.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+fn parse_all<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|x| {
+            x.iter()
+                .map(|x| x.parse().map_err(de::Error::custom))
+                .collect()
+        })
+        .transpose()
+}
@@ -0,0 +1,189 @@
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, eyre};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tracing::*;
+
+use crate::r#async::Outcome;
+use crate::interface::QualityMetric;
+use crate::state::Db;
+use crate::{ARGS, Job};
+
+/// The length of each segment measured with `--quality-samples`
+pub const SAMPLE_LENGTH: Duration = Duration::from_secs(10);
+
+static VMAF_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"VMAF score: ([\d.]+)").unwrap());
+static SSIM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"SSIM .*All:([\d.]+)").unwrap());
+static PSNR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"PSNR .*average:([\d.]+|inf)").unwrap());
+
+/// Measures the quality of each successfully encoded output against its source, prints the
+/// scores, and records them in the database. Outputs scoring below a `--quality-threshold` are
+/// listed at the end.
+pub fn evaluate(finished: &[(Job, Outcome)], db: &Db) {
+    let mut below_threshold = Vec::new();
+
+    println!("Quality:");
+    for (job, _) in finished.iter().filter(|(_, x)| x.status.success()) {
+        let name = job
+            .filename
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let mut scores = Vec::new();
+        for &metric in &ARGS.quality_metrics {
+            let score = match measure(job, metric) {
+                Ok(score) => score,
+                Err(e) => {
+                    warn!(file = %name, %metric, err = %e, "Failed to measure quality");
+                    continue;
+                }
+            };
+            if let Err(e) = db.write_quality(
+                &job.input,
+                &job.filename,
+                metric,
+                score,
+                ARGS.quality_samples.unwrap_or(0),
+            ) {
+                warn!(err = %e, "Failed to record quality score");
+            }
+
+            let threshold = ARGS
+                .quality_thresholds
+                .iter()
+                .find(|x| x.metric == metric)
+                .map(|x| x.score);
+            match threshold {
+                Some(threshold) if score < threshold => {
+                    scores.push(format!("{metric} {score:.4} (below {threshold})"));
+                    below_threshold.push(&job.filename);
+                }
+                _ => scores.push(format!("{metric} {score:.4}")),
+            }
+        }
+        println!("  {name}: {}", scores.join(", "));
+    }
+
+    if !below_threshold.is_empty() {
+        below_threshold.dedup();
+        eprintln!(
+            "{} outputs scored below the quality threshold:",
+            below_threshold.len()
+        );
+        for path in below_threshold {
+            eprintln!("  {}", path.display());
+        }
+    }
+}
+
+/// Measures one metric over the whole file, or the mean over `--quality-samples` evenly spaced
+/// segments
+fn measure(job: &Job, metric: QualityMetric) -> Result<f64> {
+    let samples = match ARGS.quality_samples {
+        Some(n) if n > 0 && job.length > SAMPLE_LENGTH * n as u32 => n,
+        _ => return measure_segment(job, metric, None),
+    };
+
+    let spacing = (job.length - SAMPLE_LENGTH) / (samples as u32 + 1);
+    let mut total = 0.0;
+    for i in 1..=samples {
+        total += measure_segment(job, metric, Some(spacing * i as u32))?;
+    }
+    Ok(total / samples as f64)
+}
+
+fn measure_segment(job: &Job, metric: QualityMetric, start: Option<Duration>) -> Result<f64> {
+    let length = start.map(|_| SAMPLE_LENGTH);
+    compare(
+        Clip {
+            path: &job.filename,
+            start,
+            length,
+        },
+        Clip {
+            path: &job.input,
+            start,
+            length,
+        },
+        job.video_index,
+        job.reference_filter.as_deref(),
+        metric,
+    )
+}
+
+/// A file to compare, or part of one
+#[derive(Debug, Clone, Copy)]
+pub struct Clip<'a> {
+    pub path: &'a Path,
+    pub start: Option<Duration>,
+    pub length: Option<Duration>,
+}
+
+/// Measures `distorted` against `reference`. The reference's video stream number `video_index` is
+/// filtered with `reference_filter`, and the distorted clip's first video stream is scaled to
+/// match it.
+pub fn compare(
+    distorted: Clip,
+    reference: Clip,
+    video_index: usize,
+    reference_filter: Option<&str>,
+    metric: QualityMetric,
+) -> Result<f64> {
+    let mut command = std::process::Command::new(&ARGS.ffmpeg_path);
+    command.args(["-hide_banner", "-nostats"]);
+    for clip in [distorted, reference] {
+        if let Some(start) = clip.start {
+            command.args(["-ss", &start.as_secs_f64().to_string()]);
+        }
+        if let Some(length) = clip.length {
+            command.args(["-t", &length.as_secs_f64().to_string()]);
+        }
+        command.arg("-i");
+        command.arg(clip.path);
+    }
+
+    // The source goes through the same filters as the encode (apart from scaling), and the output
+    // is scaled back to its size, so the frames line up
+    let reference = reference_filter
+        .map(|x| format!("{x},"))
+        .unwrap_or_default();
+    let filter = match metric {
+        QualityMetric::Vmaf => format!("libvmaf=n_threads={}", num_cpus::get()),
+        QualityMetric::Ssim => "ssim".to_owned(),
+        QualityMetric::Psnr => "psnr".to_owned(),
+    };
+    command.arg("-lavfi");
+    command.arg(format!(
+        "[1:{index}]{reference}format=yuv420p,setpts=PTS-STARTPTS[ref0];\
+         [0:v:0]format=yuv420p,setpts=PTS-STARTPTS[dist0];\
+         [dist0][ref0]scale2ref=flags=bicubic[dist][ref];\
+         [dist][ref]{filter}",
+        index = video_index
+    ));
+    command.args(["-f", "null", "-"]);
+
+    trace!(?command, "Measuring quality");
+    let output = command.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(eyre!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            stderr.lines().last().unwrap_or_default()
+        ));
+    }
+
+    let regex = match metric {
+        QualityMetric::Vmaf => &VMAF_REGEX,
+        QualityMetric::Ssim => &SSIM_REGEX,
+        QualityMetric::Psnr => &PSNR_REGEX,
+    };
+    let score = regex
+        .captures_iter(&stderr)
+        .last()
+        .and_then(|x| x[1].parse().ok())
+        .ok_or_else(|| eyre!("No {metric} score in ffmpeg output"))?;
+    Ok(score)
+}
@@ -1,12 +1,154 @@
-use crate::{ARGS, Result, tv::TVOptions};
+use crate::{
+    ARGS, Result, episodes::Episode, history::HistoryEntry, input::ScanType,
+    interface::QualityMetric, tv::TVOptions,
+};
 
 use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use rusqlite::{Connection, OptionalExtension, params};
-use tabular::{Table, row};
 use tap::Tap;
 use tracing::*;
 
+/// The steps to bring the database from each schema version to the next. Version `n` has had the
+/// first `n` steps applied, and is stored in `PRAGMA user_version`.
+///
+/// Databases created before versioning have version 0, but may already have any of the tables
+/// created up to the `conversions` step, so those steps must not fail if their tables exist.
+/// Later steps only run on versioned databases. Once released, a step must never change; add a
+/// new one instead.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS entries (
+         title   TEXT    PRIMARY KEY,
+         season  INTEGER,
+         episode INTEGER
+     ) STRICT;
+     CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5(
+         title,
+         content='entries',
+         content_rowid='rowid',
+         tokenize='porter'  -- removes punctuation & normalises words
+     );
+
+     -- Triggers to keep FTS index in sync with the content table
+     CREATE TRIGGER IF NOT EXISTS entries_ai AFTER INSERT ON entries BEGIN
+       INSERT INTO entries_fts(rowid, title) VALUES (new.rowid, new.title);
+     END;
+     CREATE TRIGGER IF NOT EXISTS entries_ad AFTER DELETE ON entries BEGIN
+       INSERT INTO entries_fts(entries_fts, rowid, title) VALUES('delete', old.rowid, old.title);
+     END;
+     CREATE TRIGGER IF NOT EXISTS entries_au AFTER UPDATE ON entries BEGIN
+       INSERT INTO entries_fts(entries_fts, rowid, title) VALUES('delete', old.rowid, old.title);
+       INSERT INTO entries_fts(rowid, title) VALUES (new.rowid, new.title);
+     END;",
+    "CREATE TABLE IF NOT EXISTS scan_types (
+         path      TEXT    NOT NULL,
+         size      INTEGER NOT NULL,
+         modified  INTEGER NOT NULL,
+         stream    INTEGER NOT NULL,
+         scan_type TEXT    NOT NULL,
+         PRIMARY KEY (path, stream)
+     ) STRICT;",
+    "CREATE TABLE IF NOT EXISTS quality (
+         output   TEXT    NOT NULL,
+         source   TEXT    NOT NULL,
+         metric   TEXT    NOT NULL,
+         score    REAL    NOT NULL,
+         samples  INTEGER NOT NULL,  -- 0 if measured over the whole file
+         measured INTEGER NOT NULL,  -- unix timestamp
+         PRIMARY KEY (output, metric)
+     ) STRICT;",
+    "CREATE TABLE IF NOT EXISTS crf_samples (
+         path     TEXT    NOT NULL,
+         size     INTEGER NOT NULL,
+         modified INTEGER NOT NULL,
+         settings TEXT    NOT NULL,
+         samples  TEXT    NOT NULL,  -- JSON array of [crf, vmaf] pairs
+         PRIMARY KEY (path, settings)
+     ) STRICT;",
+    "CREATE TABLE IF NOT EXISTS episodes (
+         show     TEXT    NOT NULL COLLATE NOCASE,
+         season   INTEGER NOT NULL,
+         episode  INTEGER NOT NULL,
+         title    TEXT    NOT NULL,
+         absolute INTEGER,
+         aired    TEXT,              -- YYYY-MM-DD
+         PRIMARY KEY (show, season, episode)
+     ) STRICT;",
+    "CREATE TABLE IF NOT EXISTS seasons (
+         show     TEXT    NOT NULL COLLATE NOCASE,
+         season   INTEGER NOT NULL,
+         episodes INTEGER NOT NULL,
+         PRIMARY KEY (show, season)
+     ) STRICT;",
+    "CREATE TABLE IF NOT EXISTS history (
+         id          INTEGER PRIMARY KEY,
+         source      TEXT    NOT NULL,
+         source_size INTEGER NOT NULL,
+         source_hash TEXT    NOT NULL,
+         output      TEXT    NOT NULL,
+         show        TEXT    COLLATE NOCASE,  -- NULL outside TV mode
+         season      INTEGER,
+         episode     INTEGER,
+         settings    TEXT    NOT NULL,
+         started     INTEGER NOT NULL,        -- unix timestamp
+         finished    INTEGER NOT NULL,        -- unix timestamp
+         exit_status INTEGER,                 -- NULL if killed by a signal
+         output_size INTEGER,
+         streams     TEXT                     -- JSON array of output streams
+     ) STRICT;
+     CREATE INDEX IF NOT EXISTS history_show ON history (show);",
+    "CREATE TABLE IF NOT EXISTS conversions (
+         fingerprint TEXT    NOT NULL,  -- see `util::fingerprint`
+         settings    TEXT    NOT NULL,
+         output      TEXT    NOT NULL,
+         converted   INTEGER NOT NULL,  -- unix timestamp
+         PRIMARY KEY (fingerprint, settings)
+     ) STRICT;",
+    "ALTER TABLE conversions ADD COLUMN analysis TEXT;  -- JSON, see `conversion::Analysis`",
+];
+
+/// Brings the database at `path` up to the latest schema version. Existing databases are copied
+/// to `<path>.v<version>.bak` first.
+fn migrate(connection: &mut Connection, path: &Path) -> Result<(), Box<dyn Error>> {
+    let version: usize =
+        connection.query_row("PRAGMA user_version;", [], |row| row.get::<_, u32>(0))? as usize;
+    if version == MIGRATIONS.len() {
+        return Ok(());
+    }
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Database {path:?} has schema version {version}, but this version of videoconverter only supports up to {}",
+            MIGRATIONS.len()
+        )
+        .into());
+    }
+
+    let is_empty = connection.query_row("SELECT count(*) = 0 FROM sqlite_schema;", [], |row| {
+        row.get::<_, bool>(0)
+    })?;
+    if !is_empty {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{version}.bak"));
+        let backup = PathBuf::from(backup);
+        if backup.exists() {
+            std::fs::remove_file(&backup)?;
+        }
+        connection.execute("VACUUM INTO ?1;", params![backup.to_string_lossy()])?;
+        info!(?backup, "Backed up database before migrating");
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        debug!(from = i, to = i + 1, "Migrating database");
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", (i + 1) as u32)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct Db {
     connection: Connection,
@@ -18,7 +160,7 @@ impl Db {
             db_path.clone()
         } else {
             dirs::cache_dir()
-                .unwrap()
+                .ok_or("No cache directory found. Pass the database path with `--statefile`")?
                 .join("videoconverter/videoconverter.sqlite")
         };
         if path.is_dir() {
@@ -29,37 +171,13 @@ impl Db {
         {
             std::fs::create_dir_all(parent)?;
         }
-        let connection = Connection::open(path)?;
-
-        connection.execute_batch(
-            "CREATE TABLE IF NOT EXISTS entries (
-                      title   TEXT    PRIMARY KEY,
-                      season  INTEGER,
-                      episode INTEGER
-                  ) STRICT;
-                  CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5(
-                      title,
-                      content='entries',
-                      content_rowid='rowid',
-                      tokenize='porter'  -- removes punctuation & normalises words
-                  );
-                  
-                  -- Triggers to keep FTS index in sync with the content table
-                  CREATE TRIGGER IF NOT EXISTS entries_ai AFTER INSERT ON entries BEGIN
-                    INSERT INTO entries_fts(rowid, title) VALUES (new.rowid, new.title);
-                  END;
-                  CREATE TRIGGER IF NOT EXISTS entries_ad AFTER DELETE ON entries BEGIN
-                    INSERT INTO entries_fts(entries_fts, rowid, title) VALUES('delete', old.rowid, old.title);
-                  END;
-                  CREATE TRIGGER IF NOT EXISTS entries_au AFTER UPDATE ON entries BEGIN
-                    INSERT INTO entries_fts(entries_fts, rowid, title) VALUES('delete', old.rowid, old.title);
-                    INSERT INTO entries_fts(rowid, title) VALUES (new.rowid, new.title);
-                  END;"
-        )?;
+        let mut connection = Connection::open(&path)?;
+        migrate(&mut connection, &path)?;
 
         Ok(Self { connection })
     }
-    pub fn find(&self, title: &str) -> Option<TVOptions> {
+    /// Finds the entry whose title best matches `title`
+    pub fn find(&self, title: &str) -> rusqlite::Result<Option<TVOptions>> {
         let title = title
             .to_lowercase()
             .replace(|c: char| !c.is_ascii_alphanumeric() && c != ' ', " ");
@@ -69,7 +187,7 @@ impl Db {
 
         trace!(query = %query, "Searching DB for title match.");
 
-        let (title, season, episode) = self
+        let entry = self
             .connection
             .query_row(
                 "SELECT entries.title, entries.season, entries.episode
@@ -87,81 +205,670 @@ impl Db {
                     Ok((title, season, episode))
                 },
             )
-            .optional()
-            .unwrap()
+            .optional()?
             .tap(|res| {
                 if let Some((title, season, episode)) = res {
                     trace!(%title, %season, %episode, "Found matching entry in DB.");
                 } else {
                     trace!("No matching TV show found in DB.");
                 }
-            })?;
+            });
 
-        Some(TVOptions {
+        Ok(entry.map(|(title, season, episode)| TVOptions {
             title,
             season,
             episode,
-        })
+            from_filenames: false,
+        }))
     }
 
-    pub fn write(&self, state: &TVOptions) {
+    pub fn write(&self, state: &TVOptions) -> rusqlite::Result<()> {
         trace!(title = %state.title, season = %state.season, episode = %state.episode, "Writing TV show state to DB.");
-        self.connection
-            .execute(
-                "INSERT INTO entries (title, season, episode)
-                     VALUES (?1, ?2, ?3)
-                     ON CONFLICT(title) DO UPDATE SET
-                        season  = excluded.season,
-                        episode = excluded.episode;
-                    ",
-                params![state.title, state.season, state.episode],
-            )
-            .unwrap();
+        self.connection.execute(
+            "INSERT INTO entries (title, season, episode)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(title) DO UPDATE SET
+                    season  = excluded.season,
+                    episode = excluded.episode;
+                ",
+            params![state.title, state.season, state.episode],
+        )?;
+        Ok(())
     }
 
-    pub fn update_episode(&self, title: &str, episode: u32) {
-        trace!(title = %title, episode = %episode, "Updating episode number in DB.");
+    /// The entry with exactly this title, ignoring case
+    pub fn entry(&self, title: &str) -> rusqlite::Result<Option<TVOptions>> {
         self.connection
-            .execute(
-                "UPDATE entries
-                     SET episode = ?1
-                     WHERE title = ?2;
+            .query_row(
+                "SELECT title, season, episode
+                     FROM entries
+                     WHERE title = ?1 COLLATE NOCASE
+                     ORDER BY title = ?1 DESC
+                     LIMIT 1;
                     ",
-                params![episode, title],
+                params![title],
+                entry_from_row,
             )
-            .unwrap();
+            .optional()
     }
 
-    pub fn dump(&self) -> Result<()> {
+    /// The entries whose titles contain `filter`, ignoring case, and optionally only those in a
+    /// season, sorted by title
+    pub fn entries(
+        &self,
+        filter: Option<&str>,
+        season: Option<u32>,
+    ) -> rusqlite::Result<Vec<TVOptions>> {
         let mut stmt = self.connection.prepare(
             "SELECT title, season, episode
-                 FROM entries",
+                 FROM entries
+                 WHERE (?1 IS NULL OR instr(lower(title), lower(?1)) > 0)
+                   AND (?2 IS NULL OR season = ?2)
+                 ORDER BY title COLLATE NOCASE;
+                ",
+        )?;
+        stmt.query_map(params![filter, season], entry_from_row)?
+            .collect()
+    }
+
+    /// Returns the number of entries removed
+    pub fn remove_entry(&self, title: &str) -> rusqlite::Result<usize> {
+        trace!(%title, "Removing entry from DB.");
+        self.connection
+            .execute("DELETE FROM entries WHERE title = ?1", params![title])
+    }
+
+    /// Renames a show everywhere it's stored. The FTS index is kept in sync by the update trigger
+    /// on `entries`.
+    pub fn rename_show(&self, from: &str, to: &str) -> rusqlite::Result<()> {
+        trace!(%from, %to, "Renaming show in DB.");
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute(
+            "UPDATE entries SET title = ?2 WHERE title = ?1;",
+            params![from, to],
         )?;
-        let mut rows = stmt.query([])?;
-        let mut table = Table::new("{:<} {:<} {:<}");
-        table.add_row(row!("Title", "Season", "Episode",));
-        table.add_heading("---");
-        while let Some(row) = rows.next()? {
-            table.add_row(row!(
-                row.get::<_, String>(0)?,
-                row.get::<_, i64>(1)?,
-                row.get::<_, i64>(2)?
-            ));
+        for table in ["episodes", "seasons", "history"] {
+            transaction.execute(
+                &format!("UPDATE {table} SET show = ?2 WHERE show = ?1;"),
+                params![from, to],
+            )?;
         }
-        println!("{}", table);
+        transaction.commit()
+    }
+
+    /// Merges the entries, episodes, season lengths and history of the shows in `from` into
+    /// `into`, which takes the state of whichever entry is furthest along. Episodes and seasons
+    /// already stored for `into` are kept.
+    pub fn merge_shows(&self, into: &TVOptions, from: &[TVOptions]) -> rusqlite::Result<()> {
+        trace!(into = %into.title, ?from, "Merging shows in DB.");
+        let transaction = self.connection.unchecked_transaction()?;
+        for entry in from {
+            transaction.execute(
+                "DELETE FROM entries WHERE title = ?1;",
+                params![entry.title],
+            )?;
+            // Episodes and seasons are matched ignoring case, so they're already shared between
+            // titles differing only in case
+            let tables = if entry.title.eq_ignore_ascii_case(&into.title) {
+                &[][..]
+            } else {
+                &["episodes", "seasons"][..]
+            };
+            for table in tables {
+                transaction.execute(
+                    &format!("UPDATE OR IGNORE {table} SET show = ?2 WHERE show = ?1;"),
+                    params![entry.title, into.title],
+                )?;
+                transaction.execute(
+                    &format!("DELETE FROM {table} WHERE show = ?1;"),
+                    params![entry.title],
+                )?;
+            }
+            transaction.execute(
+                "UPDATE history SET show = ?2 WHERE show = ?1;",
+                params![entry.title, into.title],
+            )?;
+        }
+        transaction.execute(
+            "INSERT INTO entries (title, season, episode)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(title) DO UPDATE SET
+                    season  = excluded.season,
+                    episode = excluded.episode;
+                ",
+            params![into.title, into.season, into.episode],
+        )?;
+        transaction.commit()
+    }
+
+    /// The shows with stored episodes or season lengths
+    pub fn shows_with_episodes(&self) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT show FROM episodes
+                 UNION
+                 SELECT show FROM seasons
+                 ORDER BY show;
+                ",
+        )?;
+        stmt.query_map([], |row| row.get(0))?.collect()
+    }
+
+    pub fn vacuum(&self) -> rusqlite::Result<()> {
+        trace!("Vacuuming DB.");
+        self.connection
+            .execute_batch("INSERT INTO entries_fts(entries_fts) VALUES('optimize'); VACUUM;")
+    }
+
+    /// Looks up the cached interlacing analysis of a video stream. The file's size and
+    /// modification time must match those it had when it was analysed.
+    pub fn scan_type(
+        &self,
+        path: &str,
+        size: i64,
+        modified: i64,
+        stream: usize,
+    ) -> rusqlite::Result<Option<ScanType>> {
+        let scan_type = self
+            .connection
+            .query_row(
+                "SELECT scan_type
+                     FROM scan_types
+                     WHERE path = ?1 AND stream = ?2 AND size = ?3 AND modified = ?4;
+                    ",
+                params![path, stream as i64, size, modified],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(scan_type.and_then(|x| x.parse().ok()))
+    }
+
+    pub fn write_scan_type(
+        &self,
+        path: &str,
+        size: i64,
+        modified: i64,
+        stream: usize,
+        scan_type: ScanType,
+    ) -> rusqlite::Result<()> {
+        trace!(%path, %stream, %scan_type, "Writing interlacing analysis to DB.");
+        self.connection.execute(
+            "INSERT INTO scan_types (path, size, modified, stream, scan_type)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(path, stream) DO UPDATE SET
+                    size      = excluded.size,
+                    modified  = excluded.modified,
+                    scan_type = excluded.scan_type;
+                ",
+            params![path, size, modified, stream as i64, scan_type.to_string()],
+        )?;
+        Ok(())
+    }
+
+    pub fn write_quality(
+        &self,
+        source: &Path,
+        output: &Path,
+        metric: QualityMetric,
+        score: f64,
+        samples: usize,
+    ) -> rusqlite::Result<()> {
+        trace!(?output, %metric, %score, "Writing quality score to DB.");
+        let measured = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs() as i64)
+            .unwrap_or_default();
+        self.connection.execute(
+            "INSERT INTO quality (output, source, metric, score, samples, measured)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(output, metric) DO UPDATE SET
+                    source   = excluded.source,
+                    score    = excluded.score,
+                    samples  = excluded.samples,
+                    measured = excluded.measured;
+                ",
+            params![
+                output.to_string_lossy(),
+                source.to_string_lossy(),
+                metric.to_string(),
+                score,
+                samples as i64,
+                measured
+            ],
+        )?;
         Ok(())
     }
 
-    pub fn remove_entry(&self, title: &str) -> Result<()> {
-        let count = self
+    /// Looks up the cached CRF search samples for a file encoded with the given settings. The
+    /// file's size and modification time must match those it had when it was sampled.
+    pub fn crf_samples(
+        &self,
+        path: &str,
+        size: i64,
+        modified: i64,
+        settings: &str,
+    ) -> rusqlite::Result<Option<Vec<(u8, f64)>>> {
+        let samples = self
             .connection
-            .execute("DELETE FROM entries WHERE title = ?1", params![title])?;
-        match count {
-            0 => println!("Entry for '{}' not found", title),
-            1 => println!("Successfully deleted entry for '{}'", title),
-            count => println!("Successfully deleted {} entries for '{}'", count, title),
+            .query_row(
+                "SELECT samples
+                     FROM crf_samples
+                     WHERE path = ?1 AND settings = ?2 AND size = ?3 AND modified = ?4;
+                    ",
+                params![path, settings, size, modified],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(samples.and_then(|x| serde_json::from_str(&x).ok()))
+    }
+
+    pub fn write_crf_samples(
+        &self,
+        path: &str,
+        size: i64,
+        modified: i64,
+        settings: &str,
+        samples: &[(u8, f64)],
+    ) -> rusqlite::Result<()> {
+        trace!(%path, %settings, ?samples, "Writing CRF search samples to DB.");
+        let samples = serde_json::to_string(samples).expect("Samples are serializable");
+        self.connection.execute(
+            "INSERT INTO crf_samples (path, size, modified, settings, samples)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(path, settings) DO UPDATE SET
+                    size     = excluded.size,
+                    modified = excluded.modified,
+                    samples  = excluded.samples;
+                ",
+            params![path, size, modified, settings, samples],
+        )?;
+        Ok(())
+    }
+
+    /// Looks up an episode's title. Shows are matched case-insensitively.
+    pub fn episode_title(
+        &self,
+        show: &str,
+        season: u32,
+        episode: u32,
+    ) -> rusqlite::Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT title
+                     FROM episodes
+                     WHERE show = ?1 AND season = ?2 AND episode = ?3;
+                    ",
+                params![show, season, episode],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Finds the episode of a show that aired on `date`, given as `YYYY-MM-DD`
+    pub fn episode_by_air_date(
+        &self,
+        show: &str,
+        date: &str,
+    ) -> rusqlite::Result<Option<(u32, u32)>> {
+        self.connection
+            .query_row(
+                "SELECT season, episode
+                     FROM episodes
+                     WHERE show = ?1 AND aired = ?2
+                     ORDER BY season, episode
+                     LIMIT 1;
+                    ",
+                params![show, date],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    }
+
+    pub fn episodes(&self, show: &str) -> rusqlite::Result<Vec<Episode>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT season, episode, title, absolute, aired
+                 FROM episodes
+                 WHERE show = ?1
+                 ORDER BY season, episode;
+                ",
+        )?;
+        stmt.query_map(params![show], |row| {
+            Ok(Episode {
+                season: row.get(0)?,
+                episode: row.get(1)?,
+                title: row.get(2)?,
+                absolute: row.get(3)?,
+                aired: row.get(4)?,
+            })
+        })?
+        .collect()
+    }
+
+    /// Stores the episodes of a show, replacing any already stored with the same numbers.
+    /// Returns the number written.
+    pub fn write_episodes(&self, show: &str, episodes: &[Episode]) -> rusqlite::Result<usize> {
+        trace!(%show, count = episodes.len(), "Writing episodes to DB.");
+        let transaction = self.connection.unchecked_transaction()?;
+        {
+            let mut stmt = transaction.prepare(
+                "INSERT INTO episodes (show, season, episode, title, absolute, aired)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT(show, season, episode) DO UPDATE SET
+                        title    = excluded.title,
+                        absolute = excluded.absolute,
+                        aired    = excluded.aired;
+                    ",
+            )?;
+            for episode in episodes {
+                stmt.execute(params![
+                    show,
+                    episode.season,
+                    episode.episode,
+                    episode.title,
+                    episode.absolute,
+                    episode.aired
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(episodes.len())
+    }
+
+    /// The number of episodes in each season of a show, in season order
+    pub fn season_lengths(&self, show: &str) -> rusqlite::Result<Vec<(u32, u32)>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT season, episodes
+                 FROM seasons
+                 WHERE show = ?1
+                 ORDER BY season;
+                ",
+        )?;
+        stmt.query_map(params![show], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect()
+    }
+
+    pub fn write_season_lengths(&self, show: &str, lengths: &[(u32, u32)]) -> rusqlite::Result<()> {
+        trace!(%show, ?lengths, "Writing season lengths to DB.");
+        let transaction = self.connection.unchecked_transaction()?;
+        for (season, episodes) in lengths {
+            transaction.execute(
+                "INSERT INTO seasons (show, season, episodes)
+                     VALUES (?1, ?2, ?3)
+                     ON CONFLICT(show, season) DO UPDATE SET
+                        episodes = excluded.episodes;
+                    ",
+                params![show, season, episodes],
+            )?;
         }
+        transaction.commit()
+    }
+
+    pub fn write_history(&self, entry: &HistoryEntry) -> rusqlite::Result<()> {
+        trace!(output = %entry.output, "Writing history to DB.");
+        self.connection.execute(
+            "INSERT INTO history (source, source_size, source_hash, output, show, season, episode,
+                                  settings, started, finished, exit_status, output_size, streams)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13);
+                ",
+            params![
+                entry.source,
+                entry.source_size,
+                entry.source_hash,
+                entry.output,
+                entry.show,
+                entry.season,
+                entry.episode,
+                entry.settings,
+                entry.started,
+                entry.finished,
+                entry.exit_status,
+                entry.output_size,
+                entry.streams
+            ],
+        )?;
+        Ok(())
+    }
 
+    /// The files processed for a show, or every file, most recent first
+    pub fn history(&self, show: Option<&str>) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT source, source_size, source_hash, output, show, season, episode, settings,
+                    started, finished, exit_status, output_size, streams
+                 FROM history
+                 WHERE ?1 IS NULL OR show = ?1
+                 ORDER BY finished DESC, id DESC;
+                ",
+        )?;
+        stmt.query_map(params![show], |row| {
+            Ok(HistoryEntry {
+                source: row.get(0)?,
+                source_size: row.get(1)?,
+                source_hash: row.get(2)?,
+                output: row.get(3)?,
+                show: row.get(4)?,
+                season: row.get(5)?,
+                episode: row.get(6)?,
+                settings: row.get(7)?,
+                started: row.get(8)?,
+                finished: row.get(9)?,
+                exit_status: row.get(10)?,
+                output_size: row.get(11)?,
+                streams: row.get(12)?,
+            })
+        })?
+        .collect()
+    }
+
+    /// The output an input with this fingerprint was last converted to with these settings. The
+    /// settings are a `command::conversion_key`.
+    pub fn conversion(
+        &self,
+        fingerprint: &str,
+        settings: &str,
+    ) -> rusqlite::Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT output FROM conversions WHERE fingerprint = ?1 AND settings = ?2;",
+                params![fingerprint, settings],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// The settings, output and analysis of each conversion of the input with this fingerprint,
+    /// most recent first
+    pub fn conversions(
+        &self,
+        fingerprint: &str,
+    ) -> rusqlite::Result<Vec<(String, String, Option<String>)>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT settings, output, analysis
+                 FROM conversions
+                 WHERE fingerprint = ?1
+                 ORDER BY converted DESC;
+                ",
+        )?;
+        stmt.query_map(params![fingerprint], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect()
+    }
+
+    pub fn write_conversion(
+        &self,
+        fingerprint: &str,
+        settings: &str,
+        output: &str,
+        converted: i64,
+        analysis: &str,
+    ) -> rusqlite::Result<()> {
+        trace!(fingerprint, output, "Writing conversion to DB.");
+        self.connection.execute(
+            "INSERT INTO conversions (fingerprint, settings, output, converted, analysis)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (fingerprint, settings) DO UPDATE SET
+                    output = excluded.output,
+                    converted = excluded.converted,
+                    analysis = excluded.analysis;
+                ",
+            params![fingerprint, settings, output, converted, analysis],
+        )?;
         Ok(())
     }
 }
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<TVOptions> {
+    Ok(TVOptions {
+        title: row.get(0)?,
+        season: row.get(1)?,
+        episode: row.get(2)?,
+        from_filenames: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path for a database in an empty temporary directory
+    fn temp_db(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("videoconverter-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("state.sqlite")
+    }
+
+    fn backup_path(path: &Path, version: usize) -> PathBuf {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{version}.bak"));
+        PathBuf::from(backup)
+    }
+
+    fn version(connection: &Connection) -> usize {
+        connection
+            .query_row("PRAGMA user_version;", [], |row| row.get::<_, u32>(0))
+            .unwrap() as usize
+    }
+
+    fn schema(connection: &Connection) -> Vec<(String, Option<String>)> {
+        let mut stmt = connection
+            .prepare("SELECT name, sql FROM sqlite_schema ORDER BY name;")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn latest_schema(name: &str) -> Vec<(String, Option<String>)> {
+        let path = temp_db(&format!("{name}-latest"));
+        let mut connection = Connection::open(&path).unwrap();
+        migrate(&mut connection, &path).unwrap();
+        schema(&connection)
+    }
+
+    /// A database as created by the release whose schema was the first `steps` migrations. Before
+    /// versioning, releases created their tables without setting `user_version`.
+    fn historical_db(name: &str, steps: usize, versioned: bool) -> (PathBuf, Connection) {
+        let path = temp_db(name);
+        let connection = Connection::open(&path).unwrap();
+        for migration in &MIGRATIONS[..steps] {
+            connection.execute_batch(migration).unwrap();
+        }
+        if versioned {
+            connection
+                .pragma_update(None, "user_version", steps as u32)
+                .unwrap();
+        }
+        if steps > 0 {
+            connection
+                .execute(
+                    "INSERT INTO entries (title, season, episode) VALUES ('The Office', 2, 5);",
+                    [],
+                )
+                .unwrap();
+        }
+        (path, connection)
+    }
+
+    /// The steps whose tables releases before versioning could have created
+    const UNVERSIONED_STEPS: usize = 8;
+
+    #[test]
+    fn upgrades_unversioned_schemas() {
+        let latest = latest_schema("unversioned");
+        for steps in 0..=UNVERSIONED_STEPS {
+            let (path, mut connection) =
+                historical_db(&format!("unversioned-{steps}"), steps, false);
+            migrate(&mut connection, &path).unwrap();
+
+            assert_eq!(version(&connection), MIGRATIONS.len(), "from {steps} steps");
+            assert_eq!(schema(&connection), latest, "from {steps} steps");
+            // Empty databases are new, so there's nothing to back up
+            assert_eq!(
+                backup_path(&path, 0).exists(),
+                steps > 0,
+                "from {steps} steps"
+            );
+        }
+    }
+
+    #[test]
+    fn upgrades_versioned_schemas() {
+        let latest = latest_schema("versioned");
+        for steps in 1..MIGRATIONS.len() {
+            let (path, mut connection) = historical_db(&format!("versioned-{steps}"), steps, true);
+            migrate(&mut connection, &path).unwrap();
+
+            assert_eq!(
+                version(&connection),
+                MIGRATIONS.len(),
+                "from version {steps}"
+            );
+            assert_eq!(schema(&connection), latest, "from version {steps}");
+            assert!(backup_path(&path, steps).exists(), "from version {steps}");
+        }
+    }
+
+    #[test]
+    fn keeps_entries_searchable() {
+        let (path, mut connection) = historical_db("entries", 1, false);
+        migrate(&mut connection, &path).unwrap();
+
+        let db = Db { connection };
+        let entry = db.find("the office").unwrap().unwrap();
+        assert_eq!(
+            (entry.title.as_str(), entry.season, entry.episode),
+            ("The Office", 2, 5)
+        );
+    }
+
+    #[test]
+    fn backup_is_the_old_database() {
+        let (path, mut connection) = historical_db("backup", 3, true);
+        let old = schema(&connection);
+        migrate(&mut connection, &path).unwrap();
+
+        let backup = Connection::open(backup_path(&path, 3)).unwrap();
+        assert_eq!(version(&backup), 3);
+        assert_eq!(schema(&backup), old);
+    }
+
+    #[test]
+    fn leaves_latest_version_alone() {
+        let (path, mut connection) = historical_db("latest-version", MIGRATIONS.len(), true);
+        migrate(&mut connection, &path).unwrap();
+
+        assert!(!backup_path(&path, MIGRATIONS.len()).exists());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let (path, mut connection) = historical_db("newer", MIGRATIONS.len(), true);
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+            .unwrap();
+
+        assert!(migrate(&mut connection, &path).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::fmt::Display;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// A template for output paths, relative to the output directory, e.g.
+/// `{show}/Season {season:02}/{show} - S{season:02}E{episode:02}[ - {episode_title}].{ext}`.
+///
+/// - `{field}` is replaced with the field's value. Numbers can be zero padded with `{field:02}`.
+/// - `[...]` is left out entirely if any field inside it has no value.
+/// - `/` separates directories.
+/// - `{{`, `}}`, `[[` and `]]` are literal braces and brackets.
+///
+/// Characters that aren't allowed in filenames on common filesystems are removed from field
+/// values, so a title can't add directories or produce a path Windows or SMB shares can't store.
+#[derive(Debug, Clone)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Field(Placeholder),
+    Optional(Vec<Part>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Placeholder {
+    field: Field,
+    /// The width to zero pad numbers to
+    width: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum Field {
+    /// The TV show's title
+    Show,
+    Season,
+    Episode,
+    /// The last episode in a file containing several
+    LastEpisode,
+    EpisodeTitle,
+    /// The movie's title
+    Title,
+    Year,
+    /// The input's filename, without its extension
+    Filename,
+    Ext,
+}
+
+/// The values available to a template for one output file
+#[derive(Debug, Default, Clone)]
+pub struct Values {
+    pub show: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub last_episode: Option<u32>,
+    pub episode_title: Option<String>,
+    pub title: Option<String>,
+    pub year: Option<u32>,
+    pub filename: Option<String>,
+    pub ext: Option<String>,
+}
+
+impl Values {
+    fn get(&self, placeholder: Placeholder) -> Option<String> {
+        let number = |x: Option<u32>| {
+            x.map(|x| format!("{x:0width$}", width = placeholder.width.unwrap_or(0)))
+        };
+        match placeholder.field {
+            Field::Show => self.show.as_deref().map(sanitise),
+            Field::Season => number(self.season),
+            Field::Episode => number(self.episode),
+            Field::LastEpisode => number(self.last_episode),
+            Field::EpisodeTitle => self.episode_title.as_deref().map(sanitise),
+            Field::Title => self.title.as_deref().map(sanitise),
+            Field::Year => number(self.year),
+            Field::Filename => self.filename.as_deref().map(sanitise),
+            Field::Ext => self.ext.as_deref().map(sanitise),
+        }
+        .filter(|x| !x.is_empty())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Unclosed '{0}' in template")]
+    Unclosed(char),
+    #[error("Unmatched '{0}' in template")]
+    Unmatched(char),
+    #[error("Optional sections can't be nested")]
+    NestedOptional,
+    #[error("Unknown template field '{0}'")]
+    UnknownField(String),
+    #[error("Invalid format '{0}'. Only zero padding, e.g. `:02`, is supported")]
+    InvalidFormat(String),
+    #[error(
+        "Template field '{field}' has no value for this file. Put it in `[...]` to make it optional"
+    )]
+    MissingField { field: Field },
+    #[error("Template '{0}' produces an empty path")]
+    EmptyPath(String),
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut optional: Option<Vec<Part>> = None;
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let current = optional.as_mut().unwrap_or(&mut parts);
+            match c {
+                '{' | '}' | '[' | ']' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    literal.push(c);
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(TemplateError::Unclosed('{')),
+                        }
+                    }
+                    flush(&mut literal, current);
+                    current.push(Part::Field(placeholder.parse()?));
+                }
+                '[' => {
+                    flush(&mut literal, current);
+                    if optional.is_some() {
+                        return Err(TemplateError::NestedOptional);
+                    }
+                    optional = Some(Vec::new());
+                }
+                ']' => {
+                    let Some(mut section) = optional.take() else {
+                        return Err(TemplateError::Unmatched(']'));
+                    };
+                    flush(&mut literal, &mut section);
+                    parts.push(Part::Optional(section));
+                }
+                '}' => return Err(TemplateError::Unmatched('}')),
+                c => literal.push(c),
+            }
+        }
+        if optional.is_some() {
+            return Err(TemplateError::Unclosed('['));
+        }
+        flush(&mut literal, &mut parts);
+
+        Ok(Self {
+            source: s.to_owned(),
+            parts,
+        })
+    }
+}
+
+fn flush(literal: &mut String, parts: &mut Vec<Part>) {
+    if !literal.is_empty() {
+        parts.push(Part::Literal(std::mem::take(literal)));
+    }
+}
+
+impl FromStr for Placeholder {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, format) = match s.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (s, None),
+        };
+        let field = name
+            .trim()
+            .parse()
+            .map_err(|_| TemplateError::UnknownField(name.to_owned()))?;
+        let width = format
+            .map(|format| {
+                format
+                    .strip_prefix('0')
+                    .and_then(|x| x.parse().ok())
+                    .ok_or_else(|| TemplateError::InvalidFormat(format.to_owned()))
+            })
+            .transpose()?;
+        Ok(Self { field, width })
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Template {
+    /// Fills in the template, returning a relative path
+    pub fn render(&self, values: &Values) -> Result<PathBuf, TemplateError> {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(x) => rendered.push_str(x),
+                Part::Field(placeholder) => rendered.push_str(&values.get(*placeholder).ok_or(
+                    TemplateError::MissingField {
+                        field: placeholder.field,
+                    },
+                )?),
+                Part::Optional(parts) => {
+                    let section = parts
+                        .iter()
+                        .map(|part| match part {
+                            Part::Literal(x) => Some(x.clone()),
+                            Part::Field(placeholder) => values.get(*placeholder),
+                            Part::Optional(_) => unreachable!("optional sections aren't nested"),
+                        })
+                        .collect::<Option<String>>();
+                    rendered.push_str(&section.unwrap_or_default());
+                }
+            }
+        }
+
+        // Windows doesn't allow names ending in a dot or space, and empty components (from
+        // `a//b`) or ones referring to other directories aren't wanted
+        let path = Path::new(&rendered)
+            .components()
+            .filter_map(|x| match x {
+                Component::Normal(x) => {
+                    Some(x.to_string_lossy().trim().trim_end_matches('.').to_owned())
+                }
+                _ => None,
+            })
+            .filter(|x| !x.is_empty())
+            .collect::<PathBuf>();
+
+        if path.as_os_str().is_empty() {
+            return Err(TemplateError::EmptyPath(self.source.clone()));
+        }
+        Ok(path)
+    }
+}
+
+/// Makes a value safe to use as (part of) a filename on Linux, macOS and Windows. Colons become
+/// dashes, as in `Title - Subtitle`, and other reserved characters are dropped.
+pub fn sanitise(s: &str) -> String {
+    let mut sanitised = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ':' => {
+                // "Title: Subtitle" -> "Title - Subtitle"
+                if !sanitised.ends_with(' ') {
+                    sanitised.push(' ');
+                }
+                sanitised.push('-');
+            }
+            '/' | '\\' | '|' => sanitised.push('-'),
+            '<' | '>' | '"' | '?' | '*' => {}
+            c if c.is_control() => {}
+            c => sanitised.push(c),
+        }
+    }
+    sanitised.trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::{DEFAULT_TV_TEMPLATE, DEFAULT_TV_TEMPLATE_NO_PREFIX};
+
+    fn render(template: &str, values: &Values) -> PathBuf {
+        template
+            .parse::<Template>()
+            .unwrap()
+            .render(values)
+            .unwrap()
+    }
+
+    fn episode() -> Values {
+        Values {
+            show: Some("Show".to_owned()),
+            season: Some(1),
+            episode: Some(5),
+            ext: Some("mkv".to_owned()),
+            ..Values::default()
+        }
+    }
+
+    #[test]
+    fn pads_numbers() {
+        assert_eq!(
+            render("s{season:02}e{episode:03}.{ext}", &episode()),
+            Path::new("s01e005.mkv")
+        );
+        assert_eq!(render("{season}", &episode()), Path::new("1"));
+    }
+
+    #[test]
+    fn drops_optional_sections_without_values() {
+        assert_eq!(
+            render("{show}[ - {episode_title}].{ext}", &episode()),
+            Path::new("Show.mkv")
+        );
+        let values = Values {
+            episode_title: Some("Pilot".to_owned()),
+            ..episode()
+        };
+        assert_eq!(
+            render("{show}[ - {episode_title}].{ext}", &values),
+            Path::new("Show - Pilot.mkv")
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            render("{{{show}}} [[{season}]]", &episode()),
+            Path::new("{Show} [1]")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |x: &str| x.parse::<Template>().unwrap_err();
+        assert!(matches!(parse("{nonsense}"), TemplateError::UnknownField(x) if x == "nonsense"));
+        assert!(matches!(parse("{show"), TemplateError::Unclosed('{')));
+        assert!(matches!(parse("show}"), TemplateError::Unmatched('}')));
+        assert!(matches!(parse("[{show}"), TemplateError::Unclosed('[')));
+        assert!(matches!(parse("{show}]"), TemplateError::Unmatched(']')));
+        assert!(matches!(parse("[[{show}]"), TemplateError::Unmatched(']')));
+        assert!(matches!(parse("[a[b]]"), TemplateError::NestedOptional));
+        assert!(matches!(
+            parse("{season:2}"),
+            TemplateError::InvalidFormat(_)
+        ));
+
+        let template = "{show}/{episode_title}".parse::<Template>().unwrap();
+        assert!(matches!(
+            template.render(&episode()),
+            Err(TemplateError::MissingField {
+                field: Field::EpisodeTitle
+            })
+        ));
+    }
+
+    #[test]
+    fn sanitises_values() {
+        let values = Values {
+            show: Some("AC/DC: Live <at> \"Donington\"?".to_owned()),
+            episode_title: Some("Back\\in|Black*".to_owned()),
+            ..episode()
+        };
+        assert_eq!(
+            render("{show}/{episode_title}.{ext}", &values),
+            Path::new("AC-DC - Live at Donington/Back-in-Black.mkv")
+        );
+    }
+
+    #[test]
+    fn removes_unsafe_components() {
+        let values = Values {
+            show: Some("..".to_owned()),
+            ..episode()
+        };
+        assert_eq!(
+            render("/{show}//Season {season}./x.{ext}", &values),
+            Path::new("Season 1/x.mkv")
+        );
+    }
+
+    #[test]
+    fn default_tv_templates() {
+        let values = Values {
+            last_episode: Some(6),
+            episode_title: Some("Pilot".to_owned()),
+            ..episode()
+        };
+        assert_eq!(
+            render(DEFAULT_TV_TEMPLATE, &values),
+            Path::new("Show/Season 01/Show - s01e05-e06 - Pilot.mkv")
+        );
+        assert_eq!(
+            render(DEFAULT_TV_TEMPLATE_NO_PREFIX, &episode()),
+            Path::new("Season 01/Show - s01e05.mkv")
+        );
+    }
+}
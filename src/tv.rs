@@ -1,24 +1,201 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
 
 use itertools::Itertools;
+use once_cell::sync::Lazy;
 use question::Answer;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tracing::trace;
+use tracing::{trace, warn};
 
 use crate::{ARGS, state::Db, util};
 
+/// `S01E05`, `S01E105`, `S01E01-E02`, `S01E01E02` or `S01E01-02`
+static SEASONAL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\bS(\d{1,2})[\s._-]*E(\d{1,4})(?:-?E(\d{1,4})|-(\d{1,4})\b)?").unwrap()
+});
+/// `1x05` or `1x05-1x06`
+static CROSS_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(\d{1,2})x(\d{2,3})(?:-(?:\d{1,2}x)?(\d{2,3}))?\b").unwrap());
+/// `2024.03.15`, `2024-03-15` or `2024 03 15`
+static DATE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b((?:19|20)\d\d)[.\-_ ](\d\d)[.\-_ ](\d\d)\b").unwrap());
+/// `OVA2`, `SP1` or `Special 3`, or an unnumbered `OVA` at the end of the name, e.g.
+/// `Show - OVA [1080p].mkv`. A keyword elsewhere is likely part of the title.
+static SPECIAL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(?:OVA|OAD|SP|Special)(?:[\s._-]?(\d{1,3})\b|[\s._-]*(?:[\[(]|\.\w{2,4}$|$))",
+    )
+    .unwrap()
+});
+/// `[Group] Show - 123 [1080p]`, `Show - 123v2`, `Show - 01-02` or `Show.-.03`
+static ABSOLUTE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[\s._]-[\s._](\d{1,4})(?:-(\d{1,4}))?(?:v\d+)?(?:[\s\[(.]|$)").unwrap()
+});
+
+/// Episode numbering parsed from a filename
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedEpisode {
+    /// `S01E05` or `1x05`. Specials are in season 0
+    Seasonal {
+        season: u32,
+        episodes: RangeInclusive<u32>,
+    },
+    /// Numbered from the start of the show, as anime releases often are
+    Absolute(RangeInclusive<u32>),
+    /// An `OVA` or `SP` special, which may not be numbered
+    Special(Option<u32>),
+    /// The air date of a daily show, as `YYYY-MM-DD`
+    Date(String),
+}
+
+impl ParsedEpisode {
+    /// Parses the episode number out of a filename, trying the least ambiguous formats first
+    pub fn parse(filename: &str) -> Option<Self> {
+        let number = |c: &regex::Captures, i| c.get(i).and_then(|x| x.as_str().parse().ok());
+        let range =
+            |first: u32, last: Option<u32>| first..=last.filter(|&x| x > first).unwrap_or(first);
+
+        if let Some(c) = SEASONAL_REGEX.captures(filename) {
+            let first = number(&c, 2)?;
+            return Some(Self::Seasonal {
+                season: number(&c, 1)?,
+                episodes: range(first, number(&c, 3).or_else(|| number(&c, 4))),
+            });
+        }
+        if let Some(c) = CROSS_REGEX.captures(filename) {
+            let first = number(&c, 2)?;
+            return Some(Self::Seasonal {
+                season: number(&c, 1)?,
+                episodes: range(first, number(&c, 3)),
+            });
+        }
+        if let Some(c) = DATE_REGEX.captures(filename)
+            && let (Some(month), Some(day)) = (number(&c, 2), number(&c, 3))
+            && (1..=12).contains(&month)
+            && (1..=31).contains(&day)
+        {
+            return Some(Self::Date(format!("{}-{month:02}-{day:02}", &c[1])));
+        }
+        // Before specials, as titles may contain the keywords, e.g. `Special Forces - 12`
+        if let Some(c) = ABSOLUTE_REGEX.captures(filename) {
+            let first = number(&c, 1)?;
+            return Some(Self::Absolute(range(first, number(&c, 2))));
+        }
+        if let Some(c) = SPECIAL_REGEX.captures(filename) {
+            return Some(Self::Special(number(&c, 1)));
+        }
+        None
+    }
+
+    /// The number of episodes in the file
+    pub fn count(&self) -> u32 {
+        match self {
+            Self::Seasonal { episodes, .. } | Self::Absolute(episodes) => {
+                episodes.end() - episodes.start() + 1
+            }
+            Self::Special(_) | Self::Date(_) => 1,
+        }
+    }
+
+    /// Works out the season and episode(s) of `show` this is. Dates are looked up in the episode
+    /// metadata imported into the database.
+    pub fn resolve(&self, db: &Db, show: &str) -> Option<(u32, RangeInclusive<u32>)> {
+        match self {
+            Self::Seasonal { season, episodes } => Some((*season, episodes.clone())),
+            Self::Special(number) => number.map(|x| (0, x..=x)),
+            Self::Date(date) => match db.episode_by_air_date(show, date) {
+                Ok(x) => x.map(|(season, episode)| (season, episode..=episode)),
+                Err(e) => {
+                    warn!(err = %e, "Failed to look up episode by air date");
+                    None
+                }
+            },
+            Self::Absolute(episodes) => {
+                let seasons = Seasons::load(db, show);
+                let (season, first) = seasons.to_seasonal(*episodes.start())?;
+                // A file straddling a season boundary is named after its first episode
+                let last = match seasons.to_seasonal(*episodes.end()) {
+                    Some((x, last)) if x == season => last,
+                    _ => first,
+                };
+                Some((season, first..=last))
+            }
+        }
+    }
+}
+
+/// The number of episodes in each season of a show, used to convert absolute episode numbers
+/// and to move on to the next season at the end of one
+#[derive(Debug, Default)]
+pub struct Seasons(BTreeMap<u32, u32>);
+
+impl Seasons {
+    pub fn load(db: &Db, show: &str) -> Self {
+        match db.season_lengths(show) {
+            Ok(lengths) => Self(lengths.into_iter().collect()),
+            Err(e) => {
+                warn!(err = %e, "Failed to read season lengths");
+                Self::default()
+            }
+        }
+    }
+
+    /// Converts an episode number counted from the start of the show into a season and episode.
+    /// Specials (season 0) aren't counted. Returns `None` if the seasons up to the episode aren't
+    /// all known.
+    pub fn to_seasonal(&self, absolute: u32) -> Option<(u32, u32)> {
+        let mut remaining = absolute;
+        for season in 1.. {
+            let length = *self.0.get(&season)?;
+            if remaining <= length {
+                return Some((season, remaining));
+            }
+            remaining -= length;
+        }
+        None
+    }
+
+    /// The number of episodes in a season, if known. Specials have no fixed length.
+    pub fn length(&self, season: u32) -> Option<u32> {
+        self.0.get(&season).copied().filter(|_| season != 0)
+    }
+
+    /// Moves `episode` on to the first episode of the next season if it's past the end of
+    /// `season`
+    pub fn wrap(&self, season: u32, episode: u32) -> (u32, u32) {
+        match self.0.get(&season) {
+            Some(&length) if season != 0 && episode > length => (season + 1, episode - length),
+            _ => (season, episode),
+        }
+    }
+}
+
+/// The season and episode(s) a file is named as in TV mode
+#[derive(Debug, Clone, Copy)]
+pub struct FileEpisode {
+    pub season: u32,
+    pub episode: u32,
+    /// The last episode in the file, if it contains more than one
+    pub last_episode: Option<u32>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TVOptions {
     pub title: String,
     pub season: u32,
     pub episode: u32,
+    /// Whether files are numbered by the season and episode in their names, for batches spanning
+    /// several seasons. Otherwise files are numbered consecutively from `season` and `episode`
+    #[serde(skip)]
+    pub from_filenames: bool,
 }
 
 impl TVOptions {
     pub fn from_cli(
         db: &Db,
         title: Option<&str>,
-        filename_information: &HashMap<usize, (u32, u32)>,
+        filename_information: &HashMap<usize, ParsedEpisode>,
     ) -> Option<Self> {
         if ARGS.tv_mode {
             trace!("TV Mode enabled via args");
@@ -26,40 +203,24 @@ impl TVOptions {
                 title: ARGS.title.clone().unwrap(),
                 season: ARGS.season.unwrap(),
                 episode: ARGS.episode.unwrap(),
+                from_filenames: false,
             });
         }
 
-        if !util::confirm("TV Show Mode", Some(Answer::NO)) {
+        if ARGS.movie_mode || !util::confirm("TV Show Mode", Some(Answer::NO)) {
             return None;
         }
 
-        let detected_season = (|| {
-            // my kingdom for a try blocks
-            let mut it = filename_information.iter().map(|(_, (x, _))| x).unique();
-            let season = it.next()?;
-            it.next().is_none().then_some(season)
-        })();
-
-        let detected_first_episode = {
-            let mut episodes = filename_information
-                .iter()
-                .map(|(_, (_, x))| x)
-                .collect::<Vec<_>>();
-            episodes.sort_unstable();
-            if episodes.is_empty() {
-                None
-            } else {
-                episodes
-                    .iter()
-                    .tuple_windows()
-                    .all(|(a, b)| a.abs_diff(**b) == 1)
-                    .then_some(episodes[0])
-            }
-        };
-
         let mut using_db = false;
 
-        let previous_state = title.and_then(|title| db.find(title));
+        let previous_state = title.and_then(|title| {
+            db.find(title)
+                .inspect_err(
+                    |e| warn!(err = %e, "Failed to look up the show in the state database"),
+                )
+                .ok()
+                .flatten()
+        });
         let guessed_title = previous_state.as_ref().map(|state| state.title.clone());
 
         let title = {
@@ -81,6 +242,53 @@ impl TVOptions {
             }
         };
 
+        let resolved = filename_information
+            .values()
+            .filter_map(|x| x.resolve(db, &title))
+            .collect::<Vec<_>>();
+
+        let detected_season = (|| {
+            // my kingdom for a try blocks
+            let mut it = resolved.iter().map(|(x, _)| x).unique();
+            let season = it.next()?;
+            it.next().is_none().then_some(season)
+        })();
+
+        // Files must follow on from each other, taking multi-episode files into account
+        let detected_first_episode = {
+            let mut episodes = resolved.iter().map(|(_, x)| x).collect::<Vec<_>>();
+            episodes.sort_unstable_by_key(|x| x.start());
+            if episodes.is_empty() {
+                None
+            } else {
+                episodes
+                    .iter()
+                    .tuple_windows()
+                    .all(|(a, b)| *b.start() == a.end() + 1)
+                    .then_some(*episodes[0].start())
+            }
+        };
+
+        // A batch spanning several seasons can't be numbered consecutively from one season
+        if detected_season.is_none()
+            && let Some((first_season, first)) =
+                resolved.iter().map(|(s, x)| (*s, *x.start())).min()
+            && let Some((last_season, last)) = resolved.iter().map(|(s, x)| (*s, *x.end())).max()
+            && util::confirm(
+                &format!(
+                    "Use seasons and episodes from filenames? (S{first_season:02}E{first:02} to S{last_season:02}E{last:02})"
+                ),
+                Some(Answer::YES),
+            )
+        {
+            return Some(TVOptions {
+                title,
+                season: first_season,
+                episode: first,
+                from_filenames: true,
+            });
+        }
+
         let previous_season = previous_state.as_ref().map(|state| state.season);
 
         let season = 'a: {
@@ -117,7 +325,7 @@ impl TVOptions {
                     None,
                 )
             {
-                break 'b *detected_first_episode;
+                break 'b detected_first_episode;
             };
             loop {
                 let prompt = if let Some(previous_state) = &previous_state
@@ -140,6 +348,99 @@ impl TVOptions {
             title,
             season,
             episode,
+            from_filenames: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seasonal(season: u32, episodes: RangeInclusive<u32>) -> Option<ParsedEpisode> {
+        Some(ParsedEpisode::Seasonal { season, episodes })
+    }
+
+    #[test]
+    fn seasonal_formats() {
+        let parse = ParsedEpisode::parse;
+        assert_eq!(parse("Show.S01E05.1080p.mkv"), seasonal(1, 5..=5));
+        assert_eq!(parse("Show.S01E105.mkv"), seasonal(1, 105..=105));
+        assert_eq!(parse("Show.S01E01-E02.mkv"), seasonal(1, 1..=2));
+        assert_eq!(parse("Show.S01E01E02.mkv"), seasonal(1, 1..=2));
+        assert_eq!(parse("Show.S01E01-02.mkv"), seasonal(1, 1..=2));
+        assert_eq!(parse("Show.S00E03.mkv"), seasonal(0, 3..=3));
+    }
+
+    #[test]
+    fn cross_formats() {
+        assert_eq!(ParsedEpisode::parse("Show 1x05.mkv"), seasonal(1, 5..=5));
+        assert_eq!(
+            ParsedEpisode::parse("Show 1x05-1x06.mkv"),
+            seasonal(1, 5..=6)
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            ParsedEpisode::parse("Show.2024.03.15.mkv"),
+            Some(ParsedEpisode::Date("2024-03-15".to_owned()))
+        );
+        assert_eq!(ParsedEpisode::parse("Show.2024.13.15.mkv"), None);
+    }
+
+    #[test]
+    fn specials() {
+        let parse = ParsedEpisode::parse;
+        assert_eq!(
+            parse("Show OVA2.mkv"),
+            Some(ParsedEpisode::Special(Some(2)))
+        );
+        assert_eq!(parse("Show.SP1.mkv"), Some(ParsedEpisode::Special(Some(1))));
+        assert_eq!(
+            parse("Show Special 3.mkv"),
+            Some(ParsedEpisode::Special(Some(3)))
+        );
+        assert_eq!(
+            parse("[Group] Show - OVA [1080p].mkv"),
+            Some(ParsedEpisode::Special(None))
+        );
+    }
+
+    #[test]
+    fn absolute_formats() {
+        let parse = ParsedEpisode::parse;
+        assert_eq!(
+            parse("[Group] Show - 123 [1080p].mkv"),
+            Some(ParsedEpisode::Absolute(123..=123))
+        );
+        assert_eq!(
+            parse("[Group] Show - 123v2.mkv"),
+            Some(ParsedEpisode::Absolute(123..=123))
+        );
+        assert_eq!(
+            parse("[Group] Show - 01-02 [1080p].mkv"),
+            Some(ParsedEpisode::Absolute(1..=2))
+        );
+    }
+
+    #[test]
+    fn special_keywords_in_titles() {
+        assert_eq!(
+            ParsedEpisode::parse("[Group] Special Forces - 12 [1080p].mkv"),
+            Some(ParsedEpisode::Absolute(12..=12))
+        );
+        assert_eq!(
+            ParsedEpisode::parse("Show.sp.-.03.mkv"),
+            Some(ParsedEpisode::Absolute(3..=3))
+        );
+    }
+
+    #[test]
+    fn false_positives() {
+        assert_eq!(ParsedEpisode::parse("Show.1920x1080.mkv"), None);
+        assert_eq!(ParsedEpisode::parse("Show.1080p.x264-GRP.mkv"), None);
+        assert_eq!(ParsedEpisode::parse("Show Special Forces.mkv"), None);
+    }
+}
@@ -1,3 +1,9 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use question::{Answer, Question};
 
 pub fn prompt(prompt: &str) -> String {
@@ -27,3 +33,101 @@ pub fn confirm(prompt: &str, default: Option<question::Answer>) -> bool {
         Answer::RESPONSE(x) => unreachable!("Yes/No Question shouldn't return RESPONSE: `{x}`"),
     }
 }
+
+/// Identifies a file by its canonical path, size and modification time, so the cache is
+/// invalidated if the file is replaced.
+pub fn file_key(path: &Path) -> Option<(String, i64, i64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let path = path.canonicalize().ok()?.to_string_lossy().into_owned();
+    Some((path, metadata.len() as i64, modified as i64))
+}
+
+/// The number of bytes hashed from each end of a file by `partial_hash`
+const PARTIAL_HASH_LENGTH: u64 = 1 << 20;
+
+/// Hashes the first and last megabyte of a file, which identifies a video well enough without
+/// reading gigabytes. The hash is 64-bit FNV-1a, so it's stable between builds.
+pub fn partial_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut buffer = Vec::with_capacity(PARTIAL_HASH_LENGTH as usize);
+    for start in [0, size.saturating_sub(PARTIAL_HASH_LENGTH)] {
+        buffer.clear();
+        file.seek(SeekFrom::Start(start))?;
+        (&mut file)
+            .take(PARTIAL_HASH_LENGTH)
+            .read_to_end(&mut buffer)?;
+        for &byte in &buffer {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    Ok(format!("{hash:016x}"))
+}
+
+/// Identifies a file by its contents rather than its path, so it's recognised after being renamed
+/// or moved. Formats as its size, `partial_hash` and duration in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub size: u64,
+    /// See `partial_hash`
+    pub hash: String,
+    pub duration: Duration,
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}",
+            self.size,
+            self.hash,
+            self.duration.as_millis()
+        )
+    }
+}
+
+pub fn fingerprint(path: &Path, duration: Duration) -> io::Result<Fingerprint> {
+    Ok(Fingerprint {
+        size: std::fs::metadata(path)?.len(),
+        hash: partial_hash(path)?,
+        duration,
+    })
+}
+
+pub fn unix_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Formats a unix timestamp as a UTC date and time, e.g. `2024-03-15 20:05`
+pub fn format_timestamp(timestamp: i64) -> String {
+    // Converts days since the epoch to a civil date. See
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}